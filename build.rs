@@ -1,4 +1,5 @@
 use std::env;
+use std::fs;
 use std::path::PathBuf;
 use walkdir::WalkDir;
 
@@ -20,6 +21,11 @@ fn compile_libunwind() {
     cfg.define("_LIBUNWIND_IS_NATIVE_ONLY", None);
     cfg.define("_LIBUNWIND_HAS_NO_THREADS", None);
     cfg.define("NDEBUG", None);
+    // libunwind only checks for `__ppc__`, which GCC doesn't define on
+    // 32-bit PowerPC ELF targets.
+    if env::var("CARGO_CFG_TARGET_ARCH").unwrap() == "powerpc" {
+        cfg.define("__ppc__", None);
+    }
     cfg.include("llvm-libunwind/include");
     cfg.include("include");
 
@@ -37,6 +43,20 @@ fn compile_libunwind() {
     cfg.compile("llvm_libunwind");
 }
 
+/// Returns the version of the bundled libunwind, as set in its CMake project.
+fn libunwind_version() -> String {
+    let cmake = fs::read_to_string("llvm-libunwind/CMakeLists.txt").unwrap();
+    cmake
+        .lines()
+        .find_map(|line| {
+            line.trim()
+                .strip_prefix("set(PACKAGE_VERSION ")?
+                .strip_suffix(')')
+        })
+        .expect("PACKAGE_VERSION not found in llvm-libunwind/CMakeLists.txt")
+        .to_string()
+}
+
 fn gen_libunwind_bindings() {
     let args = vec![
         "-nostdlibinc".to_string(),
@@ -65,7 +85,17 @@ fn gen_libunwind_bindings() {
 }
 
 fn main() {
-    compile_libunwind();
+    if cfg!(feature = "system-unwind") {
+        // Use the LLVM libunwind that is already linked into the program
+        // instead of bundling a second copy.
+        println!("cargo:rustc-link-lib=unwind");
+    } else {
+        compile_libunwind();
+        println!(
+            "cargo:rustc-env=MINI_BACKTRACE_LIBUNWIND_VERSION={}",
+            libunwind_version()
+        );
+    }
     gen_libunwind_bindings();
 
     for entry in WalkDir::new("llvm-libunwind")
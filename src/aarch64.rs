@@ -1,6 +1,17 @@
 use crate::uw;
 
 /// Register context from which to capture a backtrace.
+///
+/// To start unwinding, `pc` and `sp` must be valid. The frame pointer (x29)
+/// is needed if the function at `pc` uses it to locate its stack frame, and
+/// the link register (x30) is needed if `pc` is in a leaf function or in a
+/// function prologue, before the link register has been saved on the stack.
+/// The callee-saved registers x19-x28 are only used for their own values in
+/// older frames; if they are unknown, unwinding still works as long as no
+/// older function uses one of them to locate its stack frame.
+///
+/// When inspecting a suspended thread, all of these are usually available
+/// from the register save area written by the context switch.
 #[derive(Copy, Clone, Debug)]
 pub struct Context {
     /// Program counter
@@ -17,6 +28,35 @@ pub struct Context {
 }
 
 impl Context {
+    /// Creates a context with the given program counter and stack pointer,
+    /// and all other registers set to zero.
+    ///
+    /// The frame pointer and link register should then be set through `regs`
+    /// if they are known.
+    pub fn new(pc: usize, sp: usize) -> Self {
+        Self {
+            pc: pc as u64,
+            sp: sp as u64,
+            regs: [0; 31],
+            vregs: [0; 32],
+        }
+    }
+
+    /// Builds a context from the Linux `mcontext_t` of a signal frame.
+    ///
+    /// The FP/SIMD registers are stored in a variable-length record that
+    /// `libc` doesn't expose, so they are left as zero. They are not needed to
+    /// find the return address of each frame.
+    #[cfg(all(feature = "libc", target_os = "linux"))]
+    pub(crate) fn from_mcontext(mcontext: &libc::mcontext_t) -> Self {
+        Self {
+            pc: mcontext.pc,
+            sp: mcontext.sp,
+            regs: mcontext.regs,
+            vregs: [0; 32],
+        }
+    }
+
     pub(crate) unsafe fn apply(&self, cursor: *mut uw::unw_cursor_t) {
         uw::unw_set_reg(cursor, uw::UNW_REG_IP, self.pc as usize);
         uw::unw_set_reg(cursor, uw::UNW_REG_SP, self.sp as usize);
@@ -37,4 +77,27 @@ impl Context {
     pub(crate) fn ip(&self) -> usize {
         self.pc as usize
     }
+
+    pub(crate) fn sp(&self) -> usize {
+        self.sp as usize
+    }
+
+    /// Returns a copy of the context with the link register (x30) as the
+    /// program counter, for when the current function has no stack frame.
+    pub(crate) fn with_pc_from_lr(&self) -> Self {
+        Self {
+            pc: self.regs[30],
+            ..*self
+        }
+    }
+
+    /// Returns the context of the caller as described by the frame pointer
+    /// (x29) and link register (x30), without reading from the stack.
+    pub(crate) fn caller_from_link(&self) -> Self {
+        Self {
+            pc: self.regs[30],
+            sp: self.regs[29],
+            ..*self
+        }
+    }
 }
@@ -0,0 +1,157 @@
+use crate::uw;
+
+/// Register context from which to capture a backtrace.
+///
+/// To start unwinding, `pc` and `sp` must be valid. The link register is
+/// needed if `pc` is in a leaf function or in a function prologue, before the
+/// link register has been saved on the stack, and the frame pointer (r7 in
+/// Thumb code, r11 in ARM code) is needed if the function at `pc` uses it to
+/// locate its stack frame. The other callee-saved registers are only used for
+/// their own values in older frames; if they are unknown, unwinding still
+/// works as long as no older function uses one of them to locate its stack
+/// frame.
+///
+/// On Cortex-M, the registers of an interrupted context are in the exception
+/// frame pushed on the interrupted stack along with r4-r11 saved by the
+/// handler. The `lr` of the handler itself holds an `EXC_RETURN` value rather
+/// than a return address, so the stacked `lr` and `pc` must be used instead.
+/// With the `cortex-m` feature, [`Context::from_exception`] reads them from
+/// the exception frame.
+///
+/// Bit 0 of `pc` and `lr`, which is set when executing Thumb code, is ignored.
+#[derive(Copy, Clone, Debug)]
+pub struct Context {
+    /// General-purpose registers r0-r12
+    pub regs: [u32; 13],
+
+    /// Stack pointer (r13)
+    pub sp: u32,
+
+    /// Link register (r14)
+    pub lr: u32,
+
+    /// Program counter (r15)
+    pub pc: u32,
+}
+
+impl Context {
+    /// Creates a context with the given program counter and stack pointer,
+    /// and all other registers set to zero.
+    ///
+    /// The frame pointer and link register should then be set if they are
+    /// known.
+    pub fn new(pc: usize, sp: usize) -> Self {
+        Self {
+            regs: [0; 13],
+            sp: sp as u32,
+            lr: 0,
+            pc: pc as u32,
+        }
+    }
+
+    /// Builds the context of the code interrupted by an exception on
+    /// Cortex-M (ARMv6-M, ARMv7-M and ARMv8-M).
+    ///
+    /// `exc_return` is the value of `lr` on entry to the exception handler,
+    /// and `msp` and `psp` are the main and process stack pointers at that
+    /// point. `EXC_RETURN` selects the stack holding the exception frame and
+    /// whether it includes floating-point state, which together with the
+    /// alignment padding flag in the stacked `xPSR` gives the stack pointer
+    /// of the interrupted code. `r0`-`r3`, `r12`, `lr` and `pc` are read from
+    /// the exception frame.
+    ///
+    /// The hardware doesn't save r4-r11, so they are set to zero. They still
+    /// hold the values of the interrupted code on entry to the handler and
+    /// should be filled in if they were saved, in particular the frame pointer
+    /// (r7). The additional state context pushed by ARMv8-M processors with
+    /// the Security Extension, when a secure exception interrupts non-secure
+    /// code, is not supported.
+    ///
+    /// # Safety
+    ///
+    /// `exc_return` must be the `EXC_RETURN` value of the current exception
+    /// and the selected stack pointer must point to its exception frame.
+    #[cfg(feature = "cortex-m")]
+    pub unsafe fn from_exception(exc_return: u32, msp: u32, psp: u32) -> Self {
+        // SPSEL: the exception frame is on the process stack.
+        let frame = if exc_return & (1 << 2) != 0 { psp } else { msp };
+        let stacked = |i: usize| core::ptr::read((frame as *const u32).add(i));
+
+        // FType: the frame includes s0-s15, FPSCR and a reserved word.
+        let mut size = if exc_return & (1 << 4) != 0 {
+            0x20
+        } else {
+            0x68
+        };
+        // xPSR bit 9: a padding word was inserted to align the frame.
+        if stacked(7) & (1 << 9) != 0 {
+            size += 4;
+        }
+
+        let mut regs = [0; 13];
+        regs[..4].copy_from_slice(&[stacked(0), stacked(1), stacked(2), stacked(3)]);
+        regs[12] = stacked(4);
+        Self {
+            regs,
+            sp: frame + size,
+            lr: stacked(5),
+            pc: stacked(6),
+        }
+    }
+
+    /// Builds a context from the Linux `mcontext_t` of a signal frame.
+    #[cfg(all(feature = "libc", target_os = "linux"))]
+    pub(crate) fn from_mcontext(mcontext: &libc::mcontext_t) -> Self {
+        let m = mcontext;
+        let regs = [
+            m.arm_r0, m.arm_r1, m.arm_r2, m.arm_r3, m.arm_r4, m.arm_r5, m.arm_r6, m.arm_r7,
+            m.arm_r8, m.arm_r9, m.arm_r10, m.arm_fp, m.arm_ip,
+        ];
+        Self {
+            regs: regs.map(|reg| reg as u32),
+            sp: m.arm_sp as u32,
+            lr: m.arm_lr as u32,
+            pc: m.arm_pc as u32,
+        }
+    }
+
+    pub(crate) unsafe fn apply(&self, cursor: *mut uw::unw_cursor_t) {
+        uw::unw_set_reg(cursor, uw::UNW_REG_IP, self.ip());
+        uw::unw_set_reg(cursor, uw::UNW_REG_SP, self.sp as usize);
+        for i in 0..13 {
+            uw::unw_set_reg(
+                cursor,
+                (uw::UNW_ARM_R0 + i) as i32,
+                self.regs[i as usize] as usize,
+            );
+        }
+        uw::unw_set_reg(cursor, uw::UNW_ARM_LR as i32, (self.lr & !1) as usize);
+    }
+
+    pub(crate) fn ip(&self) -> usize {
+        (self.pc & !1) as usize
+    }
+
+    pub(crate) fn sp(&self) -> usize {
+        self.sp as usize
+    }
+
+    /// Returns a copy of the context with the link register as the program
+    /// counter, for when the current function has no stack frame.
+    pub(crate) fn with_pc_from_lr(&self) -> Self {
+        Self {
+            pc: self.lr,
+            ..*self
+        }
+    }
+
+    /// Returns the context of the caller as described by the Thumb frame
+    /// pointer (r7) and link register, without reading from the stack.
+    pub(crate) fn caller_from_link(&self) -> Self {
+        Self {
+            pc: self.lr,
+            sp: self.regs[7],
+            ..*self
+        }
+    }
+}
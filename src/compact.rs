@@ -0,0 +1,108 @@
+use crate::steps::{Cursor, UnwindSteps};
+use crate::{uw, FrameAction, FrameSink};
+use arrayvec::ArrayVec;
+use core::convert::TryFrom;
+use core::mem::MaybeUninit;
+
+/// A backtrace which stores each frame as a 16-bit offset from a base
+/// address.
+///
+/// This halves or quarters the size of a crash record on small
+/// microcontrollers where all code lives within a 64KiB region, such as the
+/// flash of a small Cortex-M part.
+#[derive(Clone, Debug, Default)]
+pub struct BacktraceCompact<const N: usize> {
+    /// Address that the offsets in `frames` are relative to.
+    pub base: usize,
+
+    /// Offset from `base` of the instruction pointer address of each frame,
+    /// from most recent to oldest. The addresses are adjusted in the same
+    /// way as [`Backtrace::frames`](crate::Backtrace::frames).
+    pub frames: ArrayVec<u16, N>,
+
+    /// Whether any frames have been omitted due to exceeding the capacity of
+    /// the `ArrayVec`, or because unwinding stopped after
+    /// [`MAX_STEPS`](crate::MAX_STEPS) frames.
+    pub frames_omitted: bool,
+
+    /// Whether any frames have been left out because they were not within
+    /// 64KiB above `base`.
+    pub out_of_range: bool,
+}
+
+impl<const N: usize> BacktraceCompact<N> {
+    /// Captures a backtrace from the current call point, storing frames as
+    /// offsets from `base`.
+    ///
+    /// The first frame of the backtrace is the caller of
+    /// `BacktraceCompact::capture`. Frames which don't fit in a 16-bit offset
+    /// are not recorded, don't count towards the `N` limit and cause
+    /// `out_of_range` to be set.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture(base: usize) -> Self {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self {
+                base,
+                ..Self::default()
+            };
+            result.fill_from_cursor(&mut Cursor::new(unw_cursor.as_mut_ptr()));
+            result
+        }
+    }
+
+    fn fill_from_cursor(&mut self, cursor: &mut impl UnwindSteps) {
+        if !crate::fill_frames(self, cursor, true, |_, _| FrameAction::Record) {
+            self.frames_omitted = true;
+        }
+    }
+}
+
+impl<const N: usize> FrameSink for BacktraceCompact<N> {
+    fn push(&mut self, ip: usize) -> bool {
+        match u16::try_from(ip.wrapping_sub(self.base)) {
+            Ok(offset) => self.frames.try_push(offset).is_ok(),
+            Err(_) => {
+                self.out_of_range = true;
+                true
+            }
+        }
+    }
+}
+
+#[test]
+fn compact() {
+    use crate::steps::{MockFrame, MockSteps};
+    let frame = |ip| MockFrame {
+        ip,
+        ..Default::default()
+    };
+    let frames = [frame(0x1_0011), frame(0x2_0001), frame(0x1_fff1)];
+    let mut bt = BacktraceCompact::<4> {
+        base: 0x1_0000,
+        ..Default::default()
+    };
+    bt.fill_from_cursor(&mut MockSteps::new(&frames));
+    assert_eq!(bt.frames.as_slice(), [0x10, 0xfff0]);
+    assert!(bt.out_of_range);
+    assert!(!bt.frames_omitted);
+}
+
+#[test]
+fn compact_step_limit() {
+    use crate::steps::MockFrame;
+    let frames: [MockFrame; crate::MAX_STEPS + 1] = core::array::from_fn(|i| MockFrame {
+        ip: 0x9_0000,
+        sp: i,
+        ..Default::default()
+    });
+    let mut bt = BacktraceCompact::<4>::default();
+    bt.fill_from_cursor(&mut crate::steps::MockSteps::new(&frames));
+    assert!(bt.frames.is_empty());
+    assert!(bt.frames_omitted);
+}
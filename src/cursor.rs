@@ -0,0 +1,76 @@
+use crate::steps::{Guarded, OwnedCursor, UnwindSteps};
+use crate::{uw, Context};
+use core::mem::MaybeUninit;
+
+/// A resumable walk over the stack described by a register context.
+///
+/// Each call to [`BacktraceCursor::step`] unwinds one more frame, so the cost
+/// of unwinding a deep stack can be spread over several calls, such as
+/// successive ticks of a sampling timer. The cursor owns all of the unwinder
+/// state and can be stored between calls.
+///
+/// The registers are copied from the context when the cursor is created, but
+/// the saved registers and return addresses of older frames are read from the
+/// stack as the cursor reaches them. The stack must therefore stay exactly as
+/// it was while the cursor is in use, for example because the task it
+/// belongs to is suspended; see [`BacktraceCursor::from_context`].
+pub struct BacktraceCursor {
+    cursor: Guarded<OwnedCursor>,
+    // Instruction pointer of the context, until it has been returned.
+    first: Option<usize>,
+    done: bool,
+}
+
+impl BacktraceCursor {
+    /// Creates a cursor positioned on the given register context.
+    ///
+    /// The first frame returned by [`BacktraceCursor::step`] is the
+    /// instruction pointer address in the context. `None` is returned if no
+    /// unwinding information is found for that address, as for
+    /// [`Backtrace::capture_from_context`](crate::Backtrace::capture_from_context).
+    ///
+    /// # Safety
+    ///
+    /// The stack that `ctx` describes must not be modified or freed until the
+    /// cursor is dropped or `step` has returned `None`. In particular, this
+    /// can't be used for the stack of code that keeps running between calls
+    /// to `step`, including the caller of `from_context` itself.
+    pub unsafe fn from_context(ctx: &Context) -> Option<Self> {
+        let mut unw_context = MaybeUninit::uninit();
+        let mut result = Self {
+            cursor: Guarded::new(OwnedCursor::uninit()),
+            first: Some(ctx.ip()),
+            done: false,
+        };
+        uw::unw_getcontext(unw_context.as_mut_ptr());
+        let cursor = result.cursor.inner();
+        uw::unw_init_local(cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+        // The cursor keeps its own copy of the registers, so the context
+        // doesn't need to outlive it.
+        ctx.apply(cursor.as_mut_ptr());
+        cursor.proc_range()?;
+        Some(result)
+    }
+
+    /// Unwinds to the next frame and returns its instruction pointer address.
+    ///
+    /// The addresses are adjusted in the same way as
+    /// [`Backtrace::frames`](crate::Backtrace::frames). Returns `None` once
+    /// the end of the stack is reached, or after
+    /// [`MAX_STEPS`](crate::MAX_STEPS) frames.
+    pub fn step(&mut self) -> Option<usize> {
+        if let Some(ip) = self.first.take() {
+            return Some(ip);
+        }
+        if self.done {
+            return None;
+        }
+        if self.cursor.step() {
+            Some(self.cursor.frame_ip())
+        } else {
+            self.done = true;
+            None
+        }
+    }
+}
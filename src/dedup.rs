@@ -0,0 +1,179 @@
+use crate::steps::{Cursor, UnwindSteps};
+use crate::{uw, FrameAction, FrameSink};
+use arrayvec::ArrayVec;
+use core::mem::MaybeUninit;
+
+/// Longest sequence of frames which is detected as a recursion cycle.
+const MAX_CYCLE_LEN: usize = 4;
+
+/// A backtrace in which repeated frames from recursion are recorded once.
+///
+/// Deep recursion would otherwise fill the backtrace with the same few
+/// addresses, pushing out the outer frames which show how the recursion was
+/// entered. Here, a frame which directly repeats is stored once along with its
+/// repeat count, and so is a cycle of up to 4 frames such as mutual recursion
+/// between two functions.
+#[derive(Clone, Debug, Default)]
+pub struct BacktraceDedup<const N: usize> {
+    /// Instruction pointer address of each frame, from most recent to oldest,
+    /// along with the number of consecutive times it appeared.
+    ///
+    /// The addresses are adjusted in the same way as
+    /// [`Backtrace::frames`](crate::Backtrace::frames). Each frame of a
+    /// repeated cycle has the number of times the whole cycle appeared, so
+    /// `A B A B A B` is recorded as `(A, 3), (B, 3)`. This is
+    /// indistinguishable from `A A A B B B`, but either way the same frames
+    /// were involved in the recursion.
+    pub frames: ArrayVec<(usize, u32), N>,
+
+    /// Whether any frames have been omitted due to exceeding the capacity of
+    /// the `ArrayVec`.
+    pub frames_omitted: bool,
+}
+
+impl<const N: usize> BacktraceDedup<N> {
+    /// Captures a backtrace from the current call point, collapsing
+    /// recursion.
+    ///
+    /// The first frame of the backtrace is the caller of
+    /// `BacktraceDedup::capture`. Repeated frames don't count towards the `N`
+    /// limit.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture() -> Self {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            result.fill_from_cursor(&mut Cursor::new(unw_cursor.as_mut_ptr()));
+            result
+        }
+    }
+
+    fn fill_from_cursor(&mut self, cursor: &mut impl UnwindSteps) {
+        let mut sink = Collapse {
+            frames: &mut self.frames,
+            cycle: None,
+        };
+        if !crate::fill_frames(&mut sink, cursor, false, |_, _| FrameAction::Record) {
+            self.frames_omitted = true;
+        }
+    }
+}
+
+/// A repetition of a cycle that is currently being matched.
+#[derive(Clone, Copy)]
+struct Cycle {
+    /// Index of the first frame of the cycle.
+    start: usize,
+    /// Number of frames in the cycle.
+    len: usize,
+    /// Number of frames of the current repetition matched so far.
+    pos: usize,
+}
+
+/// Frame sink which detects and collapses repeated cycles.
+struct Collapse<'a, const N: usize> {
+    frames: &'a mut ArrayVec<(usize, u32), N>,
+    cycle: Option<Cycle>,
+}
+
+impl<const N: usize> Collapse<'_, N> {
+    /// Collapses the last frames if they end with two repetitions of the same
+    /// cycle.
+    fn detect(&mut self) {
+        let n = self.frames.len();
+        for len in 1..=MAX_CYCLE_LEN.min(n / 2) {
+            let window = &mut self.frames[n - 2 * len..];
+            if window.iter().any(|&(_, count)| count != 1) {
+                // Frames that are part of an earlier cycle can't start a new
+                // one.
+                return;
+            }
+            let (first, second) = window.split_at_mut(len);
+            if first.iter().map(|f| f.0).eq(second.iter().map(|f| f.0)) {
+                for frame in first {
+                    frame.1 = 2;
+                }
+                self.frames.truncate(n - len);
+                self.cycle = Some(Cycle {
+                    start: n - 2 * len,
+                    len,
+                    pos: 0,
+                });
+                return;
+            }
+        }
+    }
+}
+
+impl<const N: usize> FrameSink for Collapse<'_, N> {
+    fn push(&mut self, ip: usize) -> bool {
+        if let Some(mut cycle) = self.cycle {
+            if self.frames[cycle.start + cycle.pos].0 == ip {
+                cycle.pos += 1;
+                if cycle.pos == cycle.len {
+                    cycle.pos = 0;
+                    for frame in &mut self.frames[cycle.start..cycle.start + cycle.len] {
+                        frame.1 = frame.1.saturating_add(1);
+                    }
+                }
+                self.cycle = Some(cycle);
+                return true;
+            }
+
+            // The cycle ended partway through a repetition: record the frames
+            // of that partial repetition as normal.
+            self.cycle = None;
+            for i in 0..cycle.pos {
+                let ip = self.frames[cycle.start + i].0;
+                if !self.push(ip) {
+                    return false;
+                }
+            }
+        }
+
+        if self.frames.try_push((ip, 1)).is_err() {
+            return false;
+        }
+        self.detect();
+        true
+    }
+}
+
+#[test]
+fn dedup() {
+    use crate::steps::{MockFrame, MockSteps};
+    let frame = |ip| MockFrame {
+        ip,
+        ..Default::default()
+    };
+    let mut frames = [
+        frame(0xa),
+        frame(0xa),
+        frame(0xa),
+        frame(0xb),
+        frame(0xc),
+        frame(0xd),
+        frame(0xc),
+        frame(0xd),
+        frame(0xc),
+        frame(0xd),
+        frame(0xc),
+        frame(0xe),
+    ];
+    // Recursive calls have the same IP but a different SP in each frame.
+    for (i, frame) in frames.iter_mut().enumerate() {
+        frame.sp = i;
+    }
+    let mut bt = BacktraceDedup::<8>::default();
+    bt.fill_from_cursor(&mut MockSteps::new(&frames));
+    assert_eq!(
+        bt.frames.as_slice(),
+        [(0x9, 3), (0xa, 1), (0xb, 3), (0xc, 3), (0xb, 1), (0xd, 1)]
+    );
+    assert!(!bt.frames_omitted);
+}
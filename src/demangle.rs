@@ -0,0 +1,59 @@
+use arrayvec::ArrayVec;
+use core::fmt::{self, Write};
+
+/// Writes the demangled form of the symbol name `mangled` to `out`.
+///
+/// Both the legacy and v0 Rust mangling schemes are supported, with the hash
+/// suffix left out in the same way as `rustfilt`. Names which aren't mangled
+/// Rust symbols are copied unchanged. Any previous contents of `out` are
+/// cleared.
+///
+/// Returns `true` if the name didn't fit in `out` and was truncated, at a
+/// character boundary so that `out` is always valid UTF-8.
+pub fn demangle<const N: usize>(mangled: &str, out: &mut ArrayVec<u8, N>) -> bool {
+    out.clear();
+    let mut writer = Truncating {
+        out,
+        truncated: false,
+    };
+    let _ = match rustc_demangle::try_demangle(mangled) {
+        Ok(demangled) => write!(writer, "{:#}", demangled),
+        Err(_) => writer.write_str(mangled),
+    };
+    writer.truncated
+}
+
+/// A `fmt::Write` which drops anything that doesn't fit in the buffer.
+struct Truncating<'a, const N: usize> {
+    out: &'a mut ArrayVec<u8, N>,
+    truncated: bool,
+}
+
+impl<const N: usize> Write for Truncating<'_, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut len = s.len().min(self.out.remaining_capacity());
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.out
+            .try_extend_from_slice(&s.as_bytes()[..len])
+            .unwrap();
+        if len < s.len() {
+            // Stop formatting, there is no point in producing more output.
+            self.truncated = true;
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn demangle_truncates() {
+    let mut out = ArrayVec::<u8, 16>::new();
+    assert!(!demangle("_ZN4core3fmt5writeE", &mut out));
+    assert_eq!(&out[..], b"core::fmt::write");
+    assert!(demangle("_ZN4core3fmt9Formatter3padE", &mut out));
+    assert_eq!(&out[..], b"core::fmt::Forma");
+    assert!(!demangle("memcpy", &mut out));
+    assert_eq!(&out[..], b"memcpy");
+}
@@ -0,0 +1,347 @@
+//! Minimal parser for the `.eh_frame` section located by the linker symbols
+//! from `eh_frame.ld`, or for the `.ARM.exidx` section on ARM.
+
+// libunwind uses ARM EHABI unwind tables instead of DWARF on ARM, so most of
+// the `.eh_frame` parser is unused there.
+#![cfg_attr(target_arch = "arm", allow(dead_code))]
+
+use crate::Backtrace;
+use core::fmt;
+use core::ops::Range;
+
+#[cfg(not(target_arch = "arm"))]
+extern "C" {
+    // Symbols defined by the linker
+    static __eh_frame_start: u8;
+    static __eh_frame_end: u8;
+}
+
+#[cfg(target_arch = "arm")]
+extern "C" {
+    // Symbols defined by the linker
+    static __exidx_start: u8;
+    static __exidx_end: u8;
+}
+
+// Pointer encodings used in `.eh_frame`.
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+const DW_EH_PE_ULEB128: u8 = 0x01;
+const DW_EH_PE_UDATA2: u8 = 0x02;
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_UDATA8: u8 = 0x04;
+const DW_EH_PE_SLEB128: u8 = 0x09;
+const DW_EH_PE_SDATA2: u8 = 0x0a;
+const DW_EH_PE_SDATA4: u8 = 0x0b;
+const DW_EH_PE_SDATA8: u8 = 0x0c;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_OMIT: u8 = 0xff;
+
+/// Cursor over a range of memory in the unwind tables.
+struct Reader {
+    pos: usize,
+    end: usize,
+}
+
+impl Reader {
+    fn bytes<const L: usize>(&mut self) -> Option<[u8; L]> {
+        if self.end - self.pos < L {
+            return None;
+        }
+        let val = unsafe { (self.pos as *const [u8; L]).read_unaligned() };
+        self.pos += L;
+        Some(val)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_ne_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes().map(u64::from_ne_bytes)
+    }
+
+    fn uleb128(&mut self) -> Option<u64> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> Option<i64> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= i64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1 << shift;
+                }
+                return Some(result);
+            }
+        }
+    }
+
+    /// Reads a pointer with the given `DW_EH_PE_*` encoding.
+    ///
+    /// Only absolute and PC-relative pointers are supported, which covers
+    /// everything emitted by LLVM and GCC into `.eh_frame`.
+    fn encoded(&mut self, encoding: u8) -> Option<usize> {
+        let pc = self.pos;
+        let val = match encoding & 0x0f {
+            DW_EH_PE_ABSPTR => self.bytes().map(usize::from_ne_bytes)?,
+            DW_EH_PE_ULEB128 => self.uleb128()? as usize,
+            DW_EH_PE_UDATA2 => self.bytes().map(u16::from_ne_bytes)? as usize,
+            DW_EH_PE_UDATA4 => self.u32()? as usize,
+            DW_EH_PE_UDATA8 => self.u64()? as usize,
+            DW_EH_PE_SLEB128 => self.sleb128()? as usize,
+            DW_EH_PE_SDATA2 => self.bytes().map(i16::from_ne_bytes)? as usize,
+            DW_EH_PE_SDATA4 => self.bytes().map(i32::from_ne_bytes)? as usize,
+            DW_EH_PE_SDATA8 => self.bytes().map(i64::from_ne_bytes)? as usize,
+            _ => return None,
+        };
+        match encoding & 0x70 {
+            0 => Some(val),
+            DW_EH_PE_PCREL => Some(pc.wrapping_add(val)),
+            _ => None,
+        }
+    }
+
+    /// Reads the length field at the start of a CIE or FDE and returns the
+    /// address of the next entry.
+    fn entry_length(&mut self) -> Option<usize> {
+        let len = match self.u32()? {
+            0xffffffff => self.u64()? as usize,
+            len => len as usize,
+        };
+        if self.end - self.pos < len {
+            return None;
+        }
+        Some(self.pos + len)
+    }
+}
+
+/// Returns the pointer encoding used for the FDEs belonging to the CIE at
+/// `cie`.
+fn fde_pointer_encoding(cie: usize, end: usize) -> Option<u8> {
+    let mut reader = Reader { pos: cie, end };
+    reader.end = reader.entry_length()?;
+    if reader.u32()? != 0 {
+        return None;
+    }
+    let version = reader.u8()?;
+    let augmentation = reader.pos;
+    while reader.u8()? != 0 {}
+    let augmentation = Reader {
+        pos: augmentation,
+        end: reader.pos - 1,
+    };
+    reader.uleb128()?; // code alignment factor
+    reader.sleb128()?; // data alignment factor
+    if version == 1 {
+        reader.u8()?; // return address register
+    } else {
+        reader.uleb128()?;
+    }
+
+    let mut augmentation = augmentation;
+    if augmentation.u8() != Some(b'z') {
+        return Some(DW_EH_PE_ABSPTR);
+    }
+    reader.uleb128()?; // augmentation data length
+    while let Some(c) = augmentation.u8() {
+        match c {
+            b'R' => return reader.u8(),
+            b'P' => {
+                let encoding = reader.u8()?;
+                reader.encoded(encoding)?;
+            }
+            b'L' => {
+                reader.u8()?;
+            }
+            b'S' | b'B' => {}
+            _ => return None,
+        }
+    }
+    Some(DW_EH_PE_ABSPTR)
+}
+
+/// Returns the bounds of the section containing the unwind tables:
+/// `.ARM.exidx` on ARM and `.eh_frame` everywhere else.
+fn unwind_table_range() -> Range<usize> {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        &__exidx_start as *const u8 as usize..&__exidx_end as *const u8 as usize
+    }
+    #[cfg(not(target_arch = "arm"))]
+    unsafe {
+        &__eh_frame_start as *const u8 as usize..&__eh_frame_end as *const u8 as usize
+    }
+}
+
+/// Calls `f` with the range of instruction addresses covered by each entry
+/// of the `.ARM.exidx` section.
+///
+/// Each entry only records the start address of its function and covers the
+/// code up to the start of the next entry. The end of the last entry isn't
+/// recorded, so it is left out.
+#[cfg(target_arch = "arm")]
+pub(crate) fn for_each_proc(mut f: impl FnMut(Range<usize>)) {
+    // Marks code which can't be unwound through, such as the space after
+    // the last function.
+    const EXIDX_CANTUNWIND: u32 = 1;
+
+    let Range { start, end } = unwind_table_range();
+    let mut reader = Reader { pos: start, end };
+    let mut prev = None;
+    loop {
+        let pos = reader.pos;
+        let (offset, data) = match (reader.u32(), reader.u32()) {
+            (Some(offset), Some(data)) => (offset, data),
+            _ => break,
+        };
+        // The start address is a 31-bit signed offset from the entry itself.
+        let proc_start = pos.wrapping_add((((offset << 1) as i32) >> 1) as usize);
+        if let Some((prev_start, true)) = prev {
+            if proc_start > prev_start {
+                f(prev_start..proc_start);
+            }
+        }
+        prev = Some((proc_start, data != EXIDX_CANTUNWIND));
+    }
+}
+
+/// Calls `f` with the range of instruction addresses covered by each FDE in
+/// the `.eh_frame` section.
+#[cfg(not(target_arch = "arm"))]
+pub(crate) fn for_each_proc(mut f: impl FnMut(Range<usize>)) {
+    let Range { start, end } = unwind_table_range();
+    let mut reader = Reader { pos: start, end };
+    while reader.end - reader.pos >= 4 {
+        let id_pos = reader.pos + 4;
+        let next = match reader.entry_length() {
+            // A zero length marks the end of the section.
+            Some(next) if next != id_pos => next,
+            _ => break,
+        };
+        match reader.u32() {
+            // A zero CIE pointer indicates a CIE, which we skip over.
+            Some(0) | None => {}
+            Some(cie_pointer) => {
+                let encoding = id_pos
+                    .checked_sub(cie_pointer as usize)
+                    .filter(|&cie| cie >= start)
+                    .and_then(|cie| fde_pointer_encoding(cie, end));
+                if let Some(encoding) = encoding.filter(|&e| e != DW_EH_PE_OMIT) {
+                    if let (Some(pc_begin), Some(pc_range)) =
+                        (reader.encoded(encoding), reader.encoded(encoding & 0x0f))
+                    {
+                        if pc_begin != 0 && pc_range != 0 {
+                            f(pc_begin..pc_begin.saturating_add(pc_range));
+                        }
+                    }
+                }
+            }
+        }
+        reader.pos = next;
+    }
+}
+
+/// Returns the total number of bytes of code covered by unwind information.
+///
+/// This is the sum of the address ranges of all FDEs in the `.eh_frame`
+/// section and can be compared against the size of the `.text` section to
+/// check that unwind tables were generated for all code in the binary. On
+/// ARM, it is instead the sum of the ranges covered by the entries of the
+/// `.ARM.exidx` section, leaving out the last function in the section since
+/// its size isn't recorded there.
+pub fn unwindable_text_bytes() -> usize {
+    let mut total = 0;
+    for_each_proc(|range| total += range.len());
+    total
+}
+
+/// A problem found by [`self_check`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetupError {
+    /// The `__eh_frame_start` and `__eh_frame_end` symbols are zero or
+    /// describe an empty section, usually because `eh_frame.ld` wasn't
+    /// passed to the linker.
+    ///
+    /// On ARM, this instead means that the `__exidx_start` and `__exidx_end`
+    /// symbols describe an empty `.ARM.exidx` section, usually because the
+    /// linker script discards it.
+    MissingEhFrame,
+    /// The unwind tables were found but a backtrace couldn't unwind past the
+    /// caller's own frame, usually because the code wasn't compiled with
+    /// `-C force-unwind-tables`.
+    NoFrames,
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(not(target_arch = "arm"))]
+            Self::MissingEhFrame => {
+                f.write_str("no .eh_frame section found, is eh_frame.ld linked?")
+            }
+            #[cfg(target_arch = "arm")]
+            Self::MissingEhFrame => f.write_str("no .ARM.exidx section found"),
+            Self::NoFrames => f.write_str("unable to unwind, is -C force-unwind-tables enabled?"),
+        }
+    }
+}
+
+/// Checks that unwinding has been set up correctly.
+///
+/// A missing linker script fragment or compiler flag results in empty
+/// backtraces rather than a build error, which is easy to miss during
+/// bring-up. This checks that the linker symbols for the `.eh_frame` section
+/// describe a plausible section and that a backtrace captured here contains
+/// both this function and its caller. On ARM, where libunwind uses the
+/// `.ARM.exidx` section instead, that section is checked.
+#[inline(never)]
+pub fn self_check() -> Result<(), SetupError> {
+    let eh_frame = unwind_table_range();
+    // The smallest possible `.eh_frame` section is a single CIE followed by
+    // the terminator, which takes well over 8 bytes, and a `.ARM.exidx`
+    // entry takes exactly 8 bytes.
+    if eh_frame.start == 0 || eh_frame.end < eh_frame.start.saturating_add(8) {
+        return Err(SetupError::MissingEhFrame);
+    }
+    let bt = Backtrace::<2>::capture();
+    core::hint::black_box(&bt);
+    if bt.frames.len() < 2 {
+        return Err(SetupError::NoFrames);
+    }
+    Ok(())
+}
+
+#[test]
+fn unwindable_text() {
+    let ip = unwindable_text as *const () as usize;
+    let mut found = false;
+    for_each_proc(|range| found |= range.contains(&ip));
+    assert!(found);
+    assert!(unwindable_text_bytes() > 0);
+}
+
+#[test]
+fn setup() {
+    assert_eq!(self_check(), Ok(()));
+}
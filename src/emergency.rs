@@ -0,0 +1,81 @@
+use crate::steps::Cursor;
+use crate::{uw, FrameAction, SliceSink};
+use core::mem::MaybeUninit;
+
+/// Scratch space for the unwinder state used by [`capture_emergency`].
+///
+/// The libunwind context and cursor are fairly large (several hundred bytes on
+/// most architectures), so this allows them to be placed in a `static` instead
+/// of on a stack that may already be exhausted.
+pub struct UnwindScratch {
+    context: MaybeUninit<uw::unw_context_t>,
+    cursor: MaybeUninit<uw::unw_cursor_t>,
+}
+
+impl UnwindScratch {
+    /// Creates new scratch space, suitable for initializing a `static`.
+    pub const fn new() -> Self {
+        Self {
+            context: MaybeUninit::uninit(),
+            cursor: MaybeUninit::uninit(),
+        }
+    }
+}
+
+impl Default for UnwindScratch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Captures a backtrace from the current call point using only static
+/// storage.
+///
+/// This is the last-resort capture path for handlers that run on a tiny
+/// emergency stack, such as a double-fault handler. The unwinder state is kept
+/// in `scratch` and the instruction pointer addresses are written to `buf`,
+/// starting with the caller of `capture_emergency`. The number of frames
+/// written is returned; if this is equal to `buf.len()` then some frames may
+/// have been omitted.
+///
+/// Nothing other than libunwind's own stepping functions is called, which
+/// still need a small amount of stack to evaluate unwind information.
+/// The same `scratch` must not be used by two captures at the same time.
+#[inline(never)]
+#[link_section = "mini_backtrace_capture"]
+pub fn capture_emergency(buf: &'static mut [usize], scratch: &'static mut UnwindScratch) -> usize {
+    unsafe {
+        uw::unw_getcontext(scratch.context.as_mut_ptr());
+        uw::unw_init_local(scratch.cursor.as_mut_ptr(), scratch.context.as_mut_ptr());
+        let mut sink = SliceSink { out: buf, len: 0 };
+        crate::fill_frames(
+            &mut sink,
+            &mut Cursor::new(scratch.cursor.as_mut_ptr()),
+            false,
+            |_, _| FrameAction::Record,
+        );
+        sink.len
+    }
+}
+
+#[test]
+#[inline(never)]
+fn emergency() {
+    use core::ptr::{addr_of, addr_of_mut};
+
+    static mut BUF: [usize; 8] = [0; 8];
+    static mut SCRATCH: UnwindScratch = UnwindScratch::new();
+    let len = unsafe { capture_emergency(&mut *addr_of_mut!(BUF), &mut *addr_of_mut!(SCRATCH)) };
+    let buf = unsafe { &*addr_of!(BUF) };
+    assert!(len > 0);
+
+    // Both captures start in this function, at different call sites, and
+    // then continue with the same callers.
+    let bt = crate::Backtrace::<8>::capture();
+    let (_, ranges) = crate::Backtrace::<1>::capture_with_proc_info();
+    assert_eq!(ranges[0].start, emergency as *const () as usize);
+    assert!(ranges[0].contains(&buf[0]));
+    assert!(ranges[0].contains(&bt.frames[0]));
+    let len = len.min(bt.frames.len());
+    assert_eq!(buf[1..len], bt.frames[1..len]);
+}
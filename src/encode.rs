@@ -0,0 +1,249 @@
+//! Compact binary encoding of backtraces.
+//!
+//! The encoding consists of:
+//! - A flags byte. Bit 0 is set if frames were omitted.
+//! - The number of frames, as a ULEB128.
+//! - The address of the first frame, as a ULEB128.
+//! - For each following frame, the difference from the previous frame's
+//!   address, zigzag-encoded as a ULEB128. Adjacent frames are usually close
+//!   together so these are typically 2 or 3 bytes each.
+
+use crate::steps::Cursor;
+use crate::{uw, Backtrace, FrameAction, FrameSink};
+use core::convert::TryFrom;
+use core::mem::MaybeUninit;
+
+/// Header flag indicating that frames were omitted.
+pub(crate) const FLAG_OMITTED: u8 = 1 << 0;
+
+/// Returns the number of bytes needed to encode `val` as a ULEB128.
+fn uleb128_len(val: u64) -> usize {
+    (64 - (val | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// Writes `val` as a ULEB128 at the start of `out`, returning the number of
+/// bytes written or `None` if it doesn't fit.
+fn write_uleb128(out: &mut [u8], mut val: u64) -> Option<usize> {
+    let len = uleb128_len(val);
+    let out = out.get_mut(..len)?;
+    for byte in &mut out[..len - 1] {
+        *byte = val as u8 | 0x80;
+        val >>= 7;
+    }
+    out[len - 1] = val as u8;
+    Some(len)
+}
+
+/// Reads a ULEB128 from `buf` at `*pos`, advancing `pos` past it. Returns
+/// `None` if the buffer ends first or the value doesn't fit in a `u64`.
+fn read_uleb128(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut val = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        if shift == 63 && byte > 1 {
+            return None;
+        }
+        val |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(val);
+        }
+        shift += 7;
+        if shift > 63 {
+            return None;
+        }
+    }
+}
+
+/// Incrementally encodes frames into a fixed-size buffer.
+pub(crate) struct Encoder<'a> {
+    out: &'a mut [u8],
+    // Frames are written after space reserved for the largest possible
+    // header, which is then moved down once the frame count is known.
+    reserved: usize,
+    pos: usize,
+    count: usize,
+    prev: usize,
+}
+
+impl<'a> Encoder<'a> {
+    pub(crate) fn new(out: &'a mut [u8]) -> Self {
+        // Every frame takes at least one byte, so the count can't exceed the
+        // length of the buffer.
+        let reserved = (1 + uleb128_len(out.len() as u64)).min(out.len());
+        Self {
+            out,
+            reserved,
+            pos: reserved,
+            count: 0,
+            prev: 0,
+        }
+    }
+
+    /// Appends a frame, returning `false` if it doesn't fit.
+    pub(crate) fn push(&mut self, ip: usize) -> bool {
+        let val = if self.count == 0 {
+            ip as u64
+        } else {
+            let delta = ip.wrapping_sub(self.prev) as isize as i64;
+            ((delta << 1) ^ (delta >> 63)) as u64
+        };
+        match write_uleb128(&mut self.out[self.pos..], val) {
+            Some(len) => {
+                self.pos += len;
+                self.count += 1;
+                self.prev = ip;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Writes the header and returns the total number of bytes written, or 0
+    /// if the buffer is too small to hold even an empty backtrace.
+    pub(crate) fn finish(self, omitted: bool) -> usize {
+        let mut header = [0; 11];
+        header[0] = if omitted { FLAG_OMITTED } else { 0 };
+        let header_len = 1 + write_uleb128(&mut header[1..], self.count as u64).unwrap();
+        if header_len > self.reserved {
+            return 0;
+        }
+        self.out.copy_within(self.reserved..self.pos, header_len);
+        self.out[..header_len].copy_from_slice(&header[..header_len]);
+        self.pos - (self.reserved - header_len)
+    }
+}
+
+impl FrameSink for Encoder<'_> {
+    fn push(&mut self, ip: usize) -> bool {
+        Encoder::push(self, ip)
+    }
+}
+
+impl<const N: usize> Backtrace<N> {
+    /// Encodes the backtrace into `out`, returning the number of bytes
+    /// written.
+    ///
+    /// This uses the same encoding as [`capture_to_fit`]. If not all frames fit
+    /// in `out` then the outermost ones are left out and the omitted flag is
+    /// set. Returns 0 only if `out` is shorter than 2 bytes.
+    pub fn encode(&self, out: &mut [u8]) -> usize {
+        let mut encoder = Encoder::new(out);
+        let mut omitted = self.frames_omitted;
+        for &ip in &self.frames {
+            if !encoder.push(ip) {
+                omitted = true;
+                break;
+            }
+        }
+        encoder.finish(omitted)
+    }
+
+    /// Decodes a backtrace written by [`Backtrace::encode`] or
+    /// [`capture_to_fit`].
+    ///
+    /// Any bytes following the encoded backtrace are ignored, so `buf` can be
+    /// an entire fixed-size log slot. Returns `None` if `buf` is truncated or
+    /// doesn't contain a valid encoding, or if it holds more than `N` frames.
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let flags = *buf.first()?;
+        if flags & !FLAG_OMITTED != 0 {
+            return None;
+        }
+        let mut pos = 1;
+        let count = usize::try_from(read_uleb128(buf, &mut pos)?).ok()?;
+        if count > N {
+            return None;
+        }
+
+        let mut result = Self {
+            frames_omitted: flags & FLAG_OMITTED != 0,
+            ..Self::default()
+        };
+        let mut prev = 0usize;
+        for i in 0..count {
+            let val = read_uleb128(buf, &mut pos)?;
+            let ip = if i == 0 {
+                usize::try_from(val).ok()?
+            } else {
+                let delta = (val >> 1) as i64 ^ -((val & 1) as i64);
+                prev.wrapping_add(delta as usize)
+            };
+            result.frames.push(ip);
+            prev = ip;
+        }
+        Some(result)
+    }
+}
+
+/// Captures a backtrace from the current call point and encodes as many
+/// frames as fit into `out`.
+///
+/// The first frame encoded is the caller of `capture_to_fit`. Frames are added
+/// until the end of the stack is reached or the next frame wouldn't fit, in
+/// which case the omitted flag is set in the header. The flag is also set if
+/// unwinding stopped after [`MAX_STEPS`](crate::MAX_STEPS) frames. The result
+/// is guaranteed to fit in `out`, which makes this suitable for telemetry
+/// channels with a fixed message size. Returns the number of bytes written,
+/// which is 0 only if `out` is shorter than 2 bytes.
+#[inline(never)]
+#[link_section = "mini_backtrace_capture"]
+pub fn capture_to_fit(out: &mut [u8]) -> usize {
+    let mut encoder = Encoder::new(out);
+    let complete = unsafe {
+        let mut unw_context = MaybeUninit::uninit();
+        let mut unw_cursor = MaybeUninit::uninit();
+        uw::unw_getcontext(unw_context.as_mut_ptr());
+        uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+        crate::fill_frames(
+            &mut encoder,
+            &mut Cursor::new(unw_cursor.as_mut_ptr()),
+            true,
+            |_, _| FrameAction::Record,
+        )
+    };
+    encoder.finish(!complete)
+}
+
+#[test]
+fn encoder() {
+    let mut buf = [0; 8];
+    let mut encoder = Encoder::new(&mut buf);
+    assert!(encoder.push(0x1000));
+    assert!(encoder.push(0x0ff0));
+    assert!(!encoder.push(0x1000_0000));
+    let len = encoder.finish(true);
+    assert_eq!(buf[..len], [FLAG_OMITTED, 2, 0x80, 0x20, 0x1f]);
+}
+
+#[test]
+fn capture_fits_buffer() {
+    let mut buf = [0; 64];
+    let len = capture_to_fit(&mut buf);
+    assert!(len > 2 && len <= buf.len());
+    assert_eq!(buf[0], 0);
+    let len = capture_to_fit(&mut buf[..4]);
+    assert!(len <= 4);
+    assert_eq!(buf[0], FLAG_OMITTED);
+}
+
+#[test]
+fn encode_round_trip() {
+    let mut bt = Backtrace::<4>::default();
+    bt.frames.push(0x1000);
+    bt.frames.push(0x0ff0);
+    bt.frames.push(0x1000_0000);
+    let mut buf = [0; 16];
+    let len = bt.encode(&mut buf);
+    let decoded = Backtrace::<4>::decode(&buf[..len]).unwrap();
+    assert_eq!(decoded.frames, bt.frames);
+    assert!(!decoded.frames_omitted);
+    assert!(Backtrace::<4>::decode(&buf[..len - 1]).is_none());
+    assert!(Backtrace::<2>::decode(&buf[..len]).is_none());
+
+    let len = bt.encode(&mut buf[..5]);
+    let decoded = Backtrace::<4>::decode(&buf[..len]).unwrap();
+    assert_eq!(decoded.frames.as_slice(), [0x1000, 0x0ff0]);
+    assert!(decoded.frames_omitted);
+}
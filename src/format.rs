@@ -0,0 +1,75 @@
+//! Formatting of backtraces through `ufmt`.
+//!
+//! This produces the same output as the usual loop over `Backtrace::frames`:
+//! one `0x`-prefixed hexadecimal address per line, followed by a marker
+//! line if any frames were omitted.
+
+use crate::Backtrace;
+use core::mem;
+use ufmt::{uDisplay, uWrite, Formatter};
+
+/// Maximum length of a formatted address, including the `0x` prefix.
+const HEX_LEN: usize = 2 + 2 * mem::size_of::<usize>();
+
+/// Formats `ip` as a `0x`-prefixed lowercase hexadecimal number.
+fn hex(ip: usize, buf: &mut [u8; HEX_LEN]) -> &str {
+    let mut pos = buf.len();
+    let mut val = ip;
+    loop {
+        pos -= 1;
+        buf[pos] = b"0123456789abcdef"[val & 0xf];
+        val >>= 4;
+        if val == 0 {
+            break;
+        }
+    }
+    pos -= 2;
+    buf[pos..pos + 2].copy_from_slice(b"0x");
+
+    // Only ASCII characters were written to the buffer.
+    unsafe { core::str::from_utf8_unchecked(&buf[pos..]) }
+}
+
+/// Writes the lines for `bt` using `write_str`.
+fn write_lines<const N: usize, E>(
+    bt: &Backtrace<N>,
+    mut write_str: impl FnMut(&str) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut buf = [0; HEX_LEN];
+    for &ip in &bt.frames {
+        write_str("  ")?;
+        write_str(hex(ip, &mut buf))?;
+        write_str("\n")?;
+    }
+    if bt.frames_omitted {
+        write_str(" ... <frames omitted>\n")?;
+    }
+    Ok(())
+}
+
+impl<const N: usize> Backtrace<N> {
+    /// Writes the backtrace to a `ufmt` writer, one frame per line.
+    ///
+    /// This is equivalent to `uwrite!(w, "{}", bt)` and never goes through
+    /// `core::fmt`.
+    pub fn uwrite<W: uWrite + ?Sized>(&self, w: &mut W) -> Result<(), W::Error> {
+        write_lines(self, |s| w.write_str(s))
+    }
+}
+
+impl<const N: usize> uDisplay for Backtrace<N> {
+    fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        write_lines(self, |s| f.write_str(s))
+    }
+}
+
+#[test]
+fn hex_format() {
+    let mut buf = [0; HEX_LEN];
+    assert_eq!(hex(0, &mut buf), "0x0");
+    assert_eq!(hex(0x1f2e, &mut buf), "0x1f2e");
+    assert_eq!(hex(usize::MAX, &mut buf).len(), HEX_LEN);
+}
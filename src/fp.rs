@@ -0,0 +1,213 @@
+//! Frame pointer based stepping, used when a frame has no unwind
+//! information.
+
+use crate::steps::{Cursor, UnwindSteps};
+use crate::uw;
+use core::mem::{self, MaybeUninit};
+use core::ops::Range;
+use core::ptr;
+
+const WORD: usize = mem::size_of::<usize>();
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        // rbp points to the saved rbp, followed by the return address.
+        const FP_REG: i32 = uw::UNW_X86_64_RBP as i32;
+        const NUM_GPRS: i32 = 16;
+        const RECORD_OFFSETS: &[isize] = &[0];
+        pub(crate) const SUPPORTED: bool = true;
+    } else if #[cfg(target_arch = "aarch64")] {
+        // x29 points to the saved x29, followed by the saved x30.
+        const FP_REG: i32 = uw::UNW_ARM64_X29 as i32;
+        const NUM_GPRS: i32 = 31;
+        const RECORD_OFFSETS: &[isize] = &[0];
+        pub(crate) const SUPPORTED: bool = true;
+    } else if #[cfg(all(target_arch = "arm", target_feature = "thumb-mode"))] {
+        // r7 points to the saved r7, followed by the saved lr.
+        const FP_REG: i32 = uw::UNW_ARM_R7 as i32;
+        const NUM_GPRS: i32 = 15;
+        const RECORD_OFFSETS: &[isize] = &[0];
+        pub(crate) const SUPPORTED: bool = true;
+    } else if #[cfg(target_arch = "arm")] {
+        // Clang sets up the frame with `push {r11, lr}; mov r11, sp`, so r11
+        // points to the saved r11, followed by the saved lr. GCC uses
+        // `push {r11, lr}; add r11, sp, #4` instead, so r11 points to the
+        // saved lr, preceded by the saved r11.
+        const FP_REG: i32 = uw::UNW_ARM_R11 as i32;
+        const NUM_GPRS: i32 = 15;
+        const RECORD_OFFSETS: &[isize] = &[0, -(WORD as isize)];
+        pub(crate) const SUPPORTED: bool = true;
+    } else if #[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))] {
+        // s0 points just past the saved s0 and ra.
+        const FP_REG: i32 = uw::UNW_RISCV_X8 as i32;
+        const NUM_GPRS: i32 = 32;
+        const RECORD_OFFSETS: &[isize] = &[-2 * WORD as isize];
+        pub(crate) const SUPPORTED: bool = true;
+    } else {
+        const FP_REG: i32 = 0;
+        const NUM_GPRS: i32 = 0;
+        const RECORD_OFFSETS: &[isize] = &[];
+        pub(crate) const SUPPORTED: bool = false;
+    }
+}
+
+/// A frame record read from the stack: the saved frame pointer and return
+/// address of the caller. The caller's stack pointer is just past the record.
+#[derive(Clone, Copy)]
+struct FrameRecord {
+    saved_fp: usize,
+    ra: usize,
+    caller_sp: usize,
+}
+
+/// Reads the frame record at `record`, if it lies within `stack` and above
+/// the current stack pointer `sp`. `record` must be aligned.
+unsafe fn read_record(record: usize, sp: usize, stack: &Range<usize>) -> Option<FrameRecord> {
+    let caller_sp = record.wrapping_add(2 * WORD);
+    if record < stack.start || stack.end.saturating_sub(record) < 2 * WORD || caller_sp <= sp {
+        return None;
+    }
+    let saved_fp = (record as *const usize).read();
+    let ra = (record as *const usize).add(1).read();
+    if ra == 0 {
+        return None;
+    }
+    Some(FrameRecord {
+        saved_fp,
+        ra,
+        caller_sp,
+    })
+}
+
+/// Reinitializes `cursor` on the caller described by `record`, with the
+/// integer registers of `old_cursor`.
+unsafe fn set_caller(cursor: *mut uw::unw_cursor_t, old_cursor: &mut Cursor, record: FrameRecord) {
+    let mut unw_context = MaybeUninit::uninit();
+    uw::unw_getcontext(unw_context.as_mut_ptr());
+    uw::unw_init_local(cursor, unw_context.as_mut_ptr());
+    for reg in 0..NUM_GPRS {
+        if let Some(val) = old_cursor.reg(reg) {
+            uw::unw_set_reg(cursor, reg, val);
+        }
+    }
+    uw::unw_set_reg(cursor, FP_REG, record.saved_fp);
+    uw::unw_set_reg(cursor, uw::UNW_REG_SP, record.caller_sp);
+    // Setting the IP looks up the unwind information for the caller, so it
+    // must be done last.
+    uw::unw_set_reg(cursor, uw::UNW_REG_IP, record.ra);
+}
+
+/// Moves `cursor` to the caller of its current frame by following the frame
+/// pointer instead of the unwind information.
+///
+/// This assumes that the current function saved a frame record in one of the
+/// standard locations for the architecture. Where there is more than one, the
+/// first whose return address has unwind information is used, or failing
+/// that the first one that could be read. Memory is only read if the frame
+/// record lies within `stack`, and the stack pointer must strictly increase
+/// so that unwinding always makes progress. Returns `false` if the frame
+/// pointer doesn't look valid, or on architectures where this isn't
+/// supported.
+///
+/// libunwind never clears its "unwind info missing" state on an existing
+/// cursor, so the cursor is reinitialized and the integer registers are
+/// copied over. Floating-point registers are lost.
+pub(crate) unsafe fn step_by_frame_pointer(
+    cursor: *mut uw::unw_cursor_t,
+    stack: &Range<usize>,
+) -> bool {
+    if !SUPPORTED {
+        return false;
+    }
+    let mut old = ptr::read(cursor);
+    let mut old_cursor = Cursor::new(&mut old);
+    let fp = match old_cursor.reg(FP_REG) {
+        Some(fp) if fp % WORD == 0 => fp,
+        _ => return false,
+    };
+    let sp = old_cursor.sp();
+
+    let mut fallback = None;
+    for &offset in RECORD_OFFSETS {
+        let record = match read_record(fp.wrapping_add(offset as usize), sp, stack) {
+            Some(record) => record,
+            None => continue,
+        };
+        set_caller(cursor, &mut old_cursor, record);
+        if RECORD_OFFSETS.len() == 1 || Cursor::new(cursor).proc_range().is_some() {
+            return true;
+        }
+        fallback.get_or_insert(record);
+    }
+    match fallback {
+        Some(record) => {
+            set_caller(cursor, &mut old_cursor, record);
+            true
+        }
+        None => false,
+    }
+}
+
+/// A libunwind cursor which falls back to the frame pointer when the current
+/// frame has no unwind information.
+pub(crate) struct ResyncCursor<'a> {
+    cursor: *mut uw::unw_cursor_t,
+    stack: &'a Range<usize>,
+    /// Whether the last step used the frame pointer.
+    pub(crate) resynced: bool,
+}
+
+impl<'a> ResyncCursor<'a> {
+    /// Wraps an initialized libunwind cursor.
+    ///
+    /// The cursor must remain valid for as long as the returned value is
+    /// used.
+    pub(crate) unsafe fn new(cursor: *mut uw::unw_cursor_t, stack: &'a Range<usize>) -> Self {
+        Self {
+            cursor,
+            stack,
+            resynced: false,
+        }
+    }
+
+    fn inner(&mut self) -> Cursor {
+        unsafe { Cursor::new(self.cursor) }
+    }
+}
+
+impl UnwindSteps for ResyncCursor<'_> {
+    fn step(&mut self) -> bool {
+        self.resynced = false;
+        let failed = match self.inner().try_step() {
+            Ok(true) => return true,
+            Ok(false) => false,
+            Err(_) => true,
+        };
+        // A frame without unwind information stops libunwind, as does an
+        // error while evaluating it, but a valid frame record may still lead
+        // to a caller that has some.
+        if (failed || self.inner().proc_range().is_none())
+            && unsafe { step_by_frame_pointer(self.cursor, self.stack) }
+        {
+            self.resynced = true;
+            return true;
+        }
+        false
+    }
+
+    fn ip(&mut self) -> usize {
+        self.inner().ip()
+    }
+
+    fn sp(&mut self) -> usize {
+        self.inner().sp()
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        self.inner().is_signal_frame()
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        self.inner().proc_range()
+    }
+}
@@ -0,0 +1,108 @@
+use crate::steps::{Cursor, UnwindSteps};
+use crate::{uw, FrameAction};
+use arrayvec::ArrayVec;
+use core::mem::MaybeUninit;
+
+/// Per-function hit counts accumulated over many captured stacks.
+///
+/// This is intended for simple sampling profilers: each call to
+/// [`capture_into_histogram`] walks the current stack and increments the count
+/// of the function of each frame on it. A function with several frames on the
+/// same stack, such as a recursive one, is counted once per frame. Memory
+/// usage is bounded by `N`, the maximum number of distinct functions that can
+/// be tracked.
+#[derive(Clone, Debug, Default)]
+pub struct FuncHistogram<const N: usize> {
+    /// Start address of each function seen so far along with the number of
+    /// frames that were attributed to it.
+    pub entries: ArrayVec<(usize, u32), N>,
+
+    /// Number of frames that could not be counted, either because the table
+    /// was full or because no unwind information was found for the frame.
+    pub dropped: u32,
+}
+
+impl<const N: usize> FuncHistogram<N> {
+    /// Creates an empty histogram.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of hits recorded for the function starting at
+    /// `start_ip`.
+    pub fn count(&self, start_ip: usize) -> u32 {
+        self.entries
+            .iter()
+            .find(|&&(ip, _)| ip == start_ip)
+            .map_or(0, |&(_, count)| count)
+    }
+
+    fn record(&mut self, start_ip: usize) {
+        if let Some(entry) = self.entries.iter_mut().find(|(ip, _)| *ip == start_ip) {
+            entry.1 = entry.1.saturating_add(1);
+        } else if self.entries.try_push((start_ip, 1)).is_err() {
+            self.dropped = self.dropped.saturating_add(1);
+        }
+    }
+
+    fn fill_from_cursor(&mut self, cursor: &mut impl UnwindSteps) {
+        // Frames are counted as they are visited rather than stored, so
+        // nothing is ever added to the sink.
+        let mut frames = ArrayVec::<usize, 0>::new();
+        crate::fill_frames(&mut frames, cursor, true, |cursor, _| {
+            match cursor.proc_range() {
+                Some(range) => self.record(range.start),
+                None => self.dropped = self.dropped.saturating_add(1),
+            }
+            FrameAction::Skip
+        });
+    }
+}
+
+/// Captures a backtrace from the current call point and adds each of its
+/// frames to `hist`.
+///
+/// Frames are keyed by the start address of their function, as reported by
+/// the unwind tables. The first frame counted is the caller of
+/// `capture_into_histogram`, excluding any wrappers from this crate.
+#[inline(never)]
+#[link_section = "mini_backtrace_capture"]
+pub fn capture_into_histogram<const N: usize>(hist: &mut FuncHistogram<N>) {
+    unsafe {
+        let mut unw_context = MaybeUninit::uninit();
+        let mut unw_cursor = MaybeUninit::uninit();
+        uw::unw_getcontext(unw_context.as_mut_ptr());
+        uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+        hist.fill_from_cursor(&mut Cursor::new(unw_cursor.as_mut_ptr()));
+    }
+}
+
+#[test]
+#[inline(never)]
+fn histogram() {
+    let mut hist = FuncHistogram::<64>::new();
+    for _ in 0..2 {
+        capture_into_histogram(&mut hist);
+    }
+    assert_eq!(hist.count(histogram as *const () as usize), 2);
+}
+
+#[test]
+fn histogram_recursion() {
+    use crate::steps::{MockFrame, MockSteps};
+    let frame = |ip, sp| MockFrame {
+        ip,
+        sp,
+        proc_range: Some(0x1000..0x2000),
+        ..Default::default()
+    };
+    let frames = [
+        frame(0x1100, 0x100),
+        frame(0x1100, 0x200),
+        MockFrame::default(),
+    ];
+    let mut hist = FuncHistogram::<4>::new();
+    hist.fill_from_cursor(&mut MockSteps::new(&frames));
+    assert_eq!(hist.count(0x1000), 2);
+    assert_eq!(hist.dropped, 1);
+}
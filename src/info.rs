@@ -0,0 +1,54 @@
+/// Information about the unwinder that this crate was built with, as returned
+/// by [`unwind_info`].
+///
+/// This is fixed at build time, so it can be included in crash reports to tell
+/// which unwinder produced a backtrace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnwindInfo {
+    /// Version of the bundled LLVM libunwind, or `None` if the `system-unwind`
+    /// feature is enabled.
+    pub libunwind_version: Option<&'static str>,
+
+    /// Whether procedure names can be looked up through the unwinder. The
+    /// bundled libunwind is built for bare-metal targets, which don't support
+    /// this.
+    pub proc_names: bool,
+
+    /// Whether stacks of other processes can be unwound. The bundled
+    /// libunwind only supports unwinding the current process.
+    pub remote_unwinding: bool,
+
+    /// Whether the unwinder can be used from several threads at once. The
+    /// bundled libunwind is built without any locking.
+    pub thread_safe: bool,
+
+    /// Whether frames without unwind information can be skipped by following
+    /// the frame pointer, as done by
+    /// [`Backtrace::capture_across_gaps`](crate::Backtrace::capture_across_gaps),
+    /// on the current target.
+    pub frame_pointer_fallback: bool,
+}
+
+/// Returns information about the unwinder that this crate was built with.
+///
+/// The capabilities of an external libunwind (with the `system-unwind`
+/// feature) aren't known when building this crate, so they are reported as
+/// `false`.
+pub fn unwind_info() -> UnwindInfo {
+    UnwindInfo {
+        libunwind_version: option_env!("MINI_BACKTRACE_LIBUNWIND_VERSION"),
+        proc_names: false,
+        remote_unwinding: false,
+        thread_safe: false,
+        frame_pointer_fallback: crate::fp::SUPPORTED,
+    }
+}
+
+#[test]
+fn unwind_info_version() {
+    let info = unwind_info();
+    assert_eq!(
+        info.libunwind_version.is_some(),
+        !cfg!(feature = "system-unwind")
+    );
+}
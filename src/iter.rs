@@ -0,0 +1,85 @@
+use crate::steps::{Guarded, OwnedCursor, UnwindSteps};
+use crate::uw;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+
+/// An iterator over the instruction pointer addresses of the frames on the
+/// current stack, from most recent to oldest.
+///
+/// Frames are unwound lazily on each call to `next`, so this composes with the
+/// standard iterator adapters without needing to choose a maximum number of
+/// frames up front, although it still ends after
+/// [`MAX_STEPS`](crate::MAX_STEPS) frames. The addresses are adjusted in the
+/// same way as [`Backtrace::frames`](crate::Backtrace::frames).
+///
+/// The iterator is single-pass: each frame is produced once, and the stack
+/// can't be walked again without calling [`BacktraceIter::with`] again. It is
+/// fine to stop iterating or to drop the iterator before the end of the stack
+/// is reached.
+///
+/// The iterator holds a libunwind cursor which refers to stack frames that
+/// are live at the point where it was created. This is only valid while those
+/// frames still exist, so a `BacktraceIter` can only be obtained inside the
+/// closure passed to [`BacktraceIter::with`]: the `'a` lifetime is unique to
+/// that call, which prevents the iterator from being returned or swapped with
+/// one from a different call.
+///
+/// ```compile_fail
+/// use mini_backtrace::BacktraceIter;
+///
+/// // The iterator must not outlive the stack frames it refers to.
+/// let iter = BacktraceIter::with(|iter| iter);
+/// ```
+pub struct BacktraceIter<'a> {
+    cursor: Guarded<OwnedCursor>,
+    done: bool,
+    // Invariant in 'a so that iterators from different calls to `with` can't
+    // be mixed.
+    _marker: PhantomData<&'a mut &'a ()>,
+}
+
+impl BacktraceIter<'_> {
+    /// Calls `f` with an iterator over the frames of the current stack.
+    ///
+    /// The first frame produced by the iterator is the caller of
+    /// `BacktraceIter::with`.
+    #[inline(never)]
+    pub fn with<R>(f: impl for<'a> FnOnce(&mut BacktraceIter<'a>) -> R) -> R {
+        let mut iter = BacktraceIter {
+            cursor: Guarded::new(OwnedCursor::uninit()),
+            done: false,
+            _marker: PhantomData,
+        };
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(iter.cursor.inner().as_mut_ptr(), unw_context.as_mut_ptr());
+        }
+        f(&mut iter)
+    }
+}
+
+impl Iterator for BacktraceIter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.done {
+            return None;
+        }
+        if self.cursor.step() {
+            Some(self.cursor.frame_ip())
+        } else {
+            self.done = true;
+            None
+        }
+    }
+}
+
+impl FusedIterator for BacktraceIter<'_> {}
+
+#[test]
+fn backtrace_iter() {
+    let frames = BacktraceIter::with(|iter| iter.count());
+    assert!(frames > 1);
+}
@@ -0,0 +1,126 @@
+use crate::steps::{Cursor, UnwindSteps};
+use crate::{uw, Backtrace, FrameAction};
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+/// A backtrace which retains enough unwinder state to inspect the registers
+/// of any of its frames later on.
+///
+/// Capturing only records the instruction pointer address of each frame,
+/// which is cheap. The registers of a particular frame can then be recovered
+/// with [`LazyBacktrace::registers_at`], which re-steps a saved copy of the
+/// cursor down to that frame. This avoids reading registers for every frame
+/// when only one of them is of interest.
+///
+/// Like [`BacktraceIter`](crate::BacktraceIter), the saved cursor is only
+/// valid while the captured stack frames still exist, so a `LazyBacktrace` can
+/// only be used inside the closure passed to [`LazyBacktrace::with`].
+pub struct LazyBacktrace<'a, const N: usize> {
+    backtrace: Backtrace<N>,
+    // Cursor positioned on the first frame of `backtrace`.
+    start: Option<MaybeUninit<uw::unw_cursor_t>>,
+    // Invariant in 'a so that registers from different calls to `with` can't
+    // be mixed.
+    _marker: PhantomData<&'a mut &'a ()>,
+}
+
+impl<const N: usize> LazyBacktrace<'_, N> {
+    /// Captures a backtrace from the current call point and calls `f` with
+    /// it.
+    ///
+    /// The first frame of the backtrace is the caller of
+    /// `LazyBacktrace::with`.
+    #[inline(never)]
+    pub fn with<R>(f: impl for<'a> FnOnce(&LazyBacktrace<'a, N>) -> R) -> R {
+        let mut lazy = LazyBacktrace {
+            backtrace: Backtrace::default(),
+            start: None,
+            _marker: PhantomData,
+        };
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let raw = unw_cursor.as_mut_ptr();
+            let start = &mut lazy.start;
+            lazy.backtrace
+                .fill_with(&mut Cursor::new(raw), true, |_, _| {
+                    // libunwind cursors don't point into themselves, so they
+                    // can be copied freely.
+                    if start.is_none() {
+                        *start = Some(MaybeUninit::new(ptr::read(raw)));
+                    }
+                    FrameAction::Record
+                });
+        }
+        f(&lazy)
+    }
+
+    /// Returns the captured backtrace.
+    pub fn backtrace(&self) -> &Backtrace<N> {
+        &self.backtrace
+    }
+}
+
+impl<'a, const N: usize> LazyBacktrace<'a, N> {
+    /// Returns the registers of the frame at index `depth` of the backtrace.
+    ///
+    /// Each call re-steps the unwinder from the first frame, so this costs as
+    /// much as unwinding `depth` frames. Returns `None` if `depth` is beyond
+    /// the end of the stack.
+    pub fn registers_at(&self, depth: usize) -> Option<FrameRegisters<'a>> {
+        let mut regs = FrameRegisters {
+            cursor: self.start?,
+            _marker: PhantomData,
+        };
+        let mut cursor = unsafe { Cursor::new(regs.cursor.as_mut_ptr()) };
+        for _ in 0..depth {
+            if !cursor.step() {
+                return None;
+            }
+        }
+        Some(regs)
+    }
+}
+
+/// Register state of a single frame of a [`LazyBacktrace`].
+pub struct FrameRegisters<'a> {
+    cursor: MaybeUninit<uw::unw_cursor_t>,
+    _marker: PhantomData<&'a mut &'a ()>,
+}
+
+impl FrameRegisters<'_> {
+    /// Returns the value of the given libunwind register in this frame.
+    ///
+    /// Register numbers are the architecture's DWARF register numbers, as used
+    /// in the `UNW_<arch>_*` constants from `libunwind.h`, or -1 and -2 for
+    /// the instruction and stack pointers. Returns `None` if the register is
+    /// not valid or its value was not preserved by the unwind information.
+    pub fn get(&mut self, reg: i32) -> Option<usize> {
+        unsafe { Cursor::new(self.cursor.as_mut_ptr()) }.reg(reg)
+    }
+
+    /// Returns the unadjusted instruction pointer of this frame.
+    pub fn ip(&mut self) -> usize {
+        self.get(uw::UNW_REG_IP).unwrap_or(0)
+    }
+
+    /// Returns the stack pointer of this frame.
+    pub fn sp(&mut self) -> usize {
+        self.get(uw::UNW_REG_SP).unwrap_or(0)
+    }
+}
+
+#[test]
+fn lazy_backtrace() {
+    LazyBacktrace::<16>::with(|lazy| {
+        let frames = &lazy.backtrace().frames;
+        assert!(frames.len() > 1);
+        let mut regs = lazy.registers_at(1).unwrap();
+        assert_eq!(regs.ip() - 1, frames[1]);
+        assert!(regs.sp() > lazy.registers_at(0).unwrap().sp());
+    });
+}
@@ -14,7 +14,10 @@
 //!
 //! 2. Several `__eh_frame_*` symbols need to be defined by the linker so that the
 //!    the unwinding tables can be located by libunwind. This can be done by
-//!    including the [`eh_frame.ld`] linker script fragment.
+//!    including the [`eh_frame.ld`] linker script fragment. On 32-bit ARM,
+//!    libunwind uses the `.ARM.exidx` section instead, which is located
+//!    through the `__exidx_start` and `__exidx_end` symbols defined by the
+//!    standard ARM linker scripts, so `eh_frame.ld` isn't used there.
 //!
 //! Both of these can be done by setting `RUSTFLAGS`:
 //!
@@ -34,6 +37,18 @@
 //! cargo build --target x86_64-unknown-linux-gnu
 //! ```
 //!
+//! If backtraces come out empty, `mini_backtrace::self_check()` reports which
+//! of these steps is missing.
+//!
+//! The capture functions of this crate are placed in a separate
+//! `mini_backtrace_capture` section, so that their frames can be left out of
+//! the backtrace. The default linker scripts keep it as its own output
+//! section; custom linker scripts must not discard it. Since the crate refers
+//! to the bounds of the section, the linker keeps all of it even with
+//! `--gc-sections`: every capture function that isn't generic ends up in the
+//! binary whether or not it is used, along with the code it calls. This
+//! costs a little flash, around 2 KiB of code on x86_64.
+//!
 //! [`eh_frame.ld`]: https://github.com/Amanieu/mini-backtrace/blob/master/eh_frame.ld
 //!
 //! ### Capturing backtraces
@@ -108,10 +123,31 @@
 //!
 //! Have a look at `examples/backtrace.rs` for a complete example.
 //!
+//! With the `executable-start` feature enabled, the same adjustment can be done
+//! at capture time with
+//! `Backtrace::capture_relative_to(mini_backtrace::module_base())`.
+//!
 //! Note that `adjust_for_pic` should *only* be called for position-independent
 //! binaries. Statically-linked binaries should emit unadjusted addresses so that
 //! the backtraces can be correctly resolved.
 //!
+//! ### Using an external libunwind
+//!
+//! By default the bundled copy of LLVM libunwind is compiled and linked
+//! statically. If the program already links LLVM libunwind, for example through
+//! its C or C++ runtime, this results in duplicate symbols. Enabling the
+//! `system-unwind` feature skips compiling the bundled copy and links against
+//! `libunwind` instead. The external library must be LLVM libunwind (or another
+//! implementation of its `unw_*` API with the same register numbering): libgcc
+//! only provides the `_Unwind_*` functions, which this crate doesn't use.
+//!
+//! The crate still allocates `unw_context_t` and `unw_cursor_t` with the sizes
+//! from the bundled libunwind 13 headers, which are only large enough for the
+//! target architecture. The external library must therefore use the same sizes:
+//! it must be built with `_LIBUNWIND_IS_NATIVE_ONLY`, as the bundled copy is,
+//! and not with support for unwinding other architectures, which makes both
+//! types larger. Otherwise libunwind writes past the end of these buffers.
+//!
 //! ### Resolving backtraces
 //!
 //! The addresses generated by `Backtrace` can be converted to human-readable
@@ -145,6 +181,12 @@
 //! main at ??:0
 //! ```
 //!
+//! The addresses can be passed to `addr2line` unchanged. The return address of each
+//! frame has already been decremented by one so that it points within the call
+//! instruction, which makes `addr2line` report the line of the call rather than the
+//! line after it, so it must not be adjusted again. The address of a frame that was
+//! interrupted by a signal or exception is not a return address and is left as-is.
+//!
 //! [rustfilt]: https://github.com/luser/rustfilt
 //!
 //! ### Backtraces from signal/interrupt handlers
@@ -153,16 +195,39 @@
 //! signal handler or interrupt handler frames. Instead, you can use
 //! `Backtrace::capture_from_context` and pass in the register state at the point
 //! where the exception occurred. In a signal handler this can be obtained through
-//! the `uc_mcontext` field of `ucontext_t`.
+//! the `uc_mcontext` field of `ucontext_t`, which `Context::from_ucontext` reads
+//! when the `ucontext` feature is enabled.
+//!
+//! On Cortex-M, enabling the `cortex-m` feature adds `Context::from_exception`,
+//! which builds the context from the exception frame that the hardware pushed on
+//! the interrupted stack.
+//!
+//! The same function can print the backtrace of another thread, such as a
+//! suspended RTOS task, since the unwinder only follows the registers it is given.
+//! `Context::new` builds a context from the saved program counter and stack
+//! pointer of the task: see the documentation of `Context` for which other
+//! registers should be filled in for a reliable backtrace.
 //!
 //! This is currently only implemented for:
 //! - AArch64
+//! - ARM (32-bit, including Cortex-M)
+//! - MIPS (32-bit)
+//! - PowerPC (32-bit & 64-bit)
 //! - RISC-V (RV32 & RV64)
 
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 use arrayvec::ArrayVec;
+use core::fmt;
 use core::mem::MaybeUninit;
+use core::ops::Range;
+#[cfg(debug_assertions)]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use steps::{CheckedCursor, Cursor, Guarded, StackBounds, StepLimit, UnwindSteps};
 
 #[allow(non_upper_case_globals)]
 #[allow(non_camel_case_types)]
@@ -172,16 +237,194 @@ mod uw {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 
+mod compact;
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "mips",
+    target_arch = "powerpc",
+    target_arch = "powerpc64",
+    target_arch = "riscv64",
+    target_arch = "riscv32"
+))]
+mod cursor;
+mod dedup;
+#[cfg(feature = "rustc-demangle")]
+mod demangle;
+mod eh_frame;
+mod emergency;
+mod encode;
+#[cfg(feature = "ufmt")]
+mod format;
+mod fp;
+mod histogram;
+mod info;
+mod iter;
+mod lazy;
+#[cfg(feature = "pprof")]
+mod pprof;
+mod producer;
+mod steps;
+mod symtab;
+#[cfg(feature = "alloc")]
+mod vec;
+
+pub use compact::BacktraceCompact;
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "mips",
+    target_arch = "powerpc",
+    target_arch = "powerpc64",
+    target_arch = "riscv64",
+    target_arch = "riscv32"
+))]
+pub use cursor::BacktraceCursor;
+pub use dedup::BacktraceDedup;
+#[cfg(feature = "rustc-demangle")]
+pub use demangle::demangle;
+pub use eh_frame::{self_check, unwindable_text_bytes, SetupError};
+pub use emergency::{capture_emergency, UnwindScratch};
+pub use encode::capture_to_fit;
+pub use histogram::{capture_into_histogram, FuncHistogram};
+pub use info::{unwind_info, UnwindInfo};
+pub use iter::BacktraceIter;
+pub use lazy::{FrameRegisters, LazyBacktrace};
+#[cfg(feature = "pprof")]
+pub use pprof::Profile;
+pub use producer::{capture_into_producer, FrameProducer};
+pub use symtab::{capture_indexed, SymbolTable};
+#[cfg(feature = "alloc")]
+pub use vec::BacktraceVec;
+
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "aarch64")] {
         mod aarch64;
         pub use aarch64::Context;
+    } else if #[cfg(target_arch = "arm")] {
+        mod arm;
+        pub use arm::Context;
+    } else if #[cfg(target_arch = "mips")] {
+        mod mips;
+        pub use mips::Context;
+    } else if #[cfg(any(target_arch = "powerpc", target_arch = "powerpc64"))] {
+        mod powerpc;
+        pub use powerpc::Context;
     } else if #[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))] {
         mod riscv;
         pub use riscv::Context;
     }
 }
 
+#[cfg(all(
+    feature = "ucontext",
+    target_os = "linux",
+    any(
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "riscv64",
+        target_arch = "riscv32"
+    )
+))]
+impl Context {
+    /// Builds a context from the `ucontext_t` passed to an `SA_SIGINFO` signal
+    /// handler.
+    ///
+    /// The registers are read from the `uc_mcontext` field using the Linux
+    /// `mcontext_t` definitions of `libc` 0.2: `regs`, `sp` and `pc` on
+    /// AArch64, `arm_r0` to `arm_pc` on ARM, and `__gregs` and the D extension
+    /// `__fpregs` on RISC-V. Registers which `libc` doesn't expose, such as the
+    /// AArch64 SIMD registers, are set to zero.
+    ///
+    /// # Safety
+    ///
+    /// `uc` must point to a valid `libc::ucontext_t`.
+    pub unsafe fn from_ucontext(uc: *const libc::ucontext_t) -> Self {
+        Self::from_mcontext(&(*uc).uc_mcontext)
+    }
+}
+
+/// Base address subtracted by `Backtrace::capture_global_adjusted`.
+static GLOBAL_PIC_BASE: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the base address that is subtracted from frame addresses by
+/// [`Backtrace::capture_global_adjusted`].
+///
+/// This is intended for position-independent executables which are loaded at
+/// a different address on each boot: the load offset can be computed once at
+/// startup and registered here instead of being passed around to every
+/// capture site. It is opt-in and has no effect on [`Backtrace::capture`] or
+/// any of the other capture functions.
+pub fn set_global_pic_base(base: usize) {
+    GLOBAL_PIC_BASE.store(base, Ordering::Relaxed);
+}
+
+/// Returns the address at which the executable was loaded.
+///
+/// This reads the `__executable_start` symbol, which is defined by the GNU and
+/// LLVM linkers when using their default linker scripts. Custom linker scripts
+/// need to define it themselves for this to link.
+#[cfg(feature = "executable-start")]
+pub fn module_base() -> usize {
+    extern "C" {
+        // Symbol defined by the linker
+        static __executable_start: [u8; 0];
+    }
+    unsafe { __executable_start.as_ptr() as usize }
+}
+
+/// Number of frames printed by [`print_backtrace_on`].
+const PANIC_FRAMES: usize = 32;
+
+/// Number of frames between the caller of [`print_backtrace_on`] and the
+/// function that panicked: the panic handler itself and `panic_fmt`.
+const PANIC_SKIP: usize = 2;
+
+/// Captures a backtrace and prints it to `w`, for use in a `#[panic_handler]`.
+///
+/// The backtrace starts at the function which called `core::panicking`'s
+/// `panic_fmt`, leaving out the panic handler and `panic_fmt` itself. This is
+/// usually the function containing the `panic!`, but panics raised by the
+/// standard library such as failed bounds checks or `unwrap` on `None` start
+/// at a small helper function in `core` instead. Up to 32 frames are printed
+/// in the same format as the `Display` implementation of [`Backtrace`].
+///
+/// Nothing is allocated and unwinding stops after [`MAX_STEPS`] frames, but a
+/// stack that is badly corrupted can still cause the unwinder itself to fault.
+///
+/// ```ignore
+/// #[panic_handler]
+/// fn panic(info: &core::panic::PanicInfo) -> ! {
+///     let mut uart = Uart::new();
+///     let _ = writeln!(uart, "{}", info);
+///     let _ = mini_backtrace::print_backtrace_on(&mut uart);
+///     loop {}
+/// }
+/// ```
+#[inline(never)]
+#[link_section = "mini_backtrace_capture"]
+pub fn print_backtrace_on<W: fmt::Write + ?Sized>(w: &mut W) -> fmt::Result {
+    let bt = unsafe {
+        let mut unw_context = MaybeUninit::uninit();
+        let mut unw_cursor = MaybeUninit::uninit();
+        uw::unw_getcontext(unw_context.as_mut_ptr());
+        uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+        let mut bt = Backtrace::<PANIC_FRAMES>::default();
+        let mut skip = PANIC_SKIP;
+        bt.fill_with(&mut Cursor::new(unw_cursor.as_mut_ptr()), false, |_, _| {
+            if skip > 0 {
+                skip -= 1;
+                FrameAction::Skip
+            } else {
+                FrameAction::Record
+            }
+        });
+        bt
+    };
+    write!(w, "{}", bt)
+}
+
 /// A backtrace consisting of a list of instruction pointer addresses.
 ///
 /// The backtrace does not allocate any memory, which allows it to be used in
@@ -191,7 +434,13 @@ cfg_if::cfg_if! {
 /// The `N` generic constant controls the maximum number of entries that should
 /// be included in the backtrace. Usually 16 frames are enough to get sufficient
 /// context from a crash.
-#[derive(Clone, Debug, Default)]
+///
+/// libunwind's cache of unwind table lookups is not used: it can only grow by
+/// allocating memory, which is disabled in this build. Every capture therefore
+/// searches the unwind tables from scratch, so its cost depends only on the
+/// stack being unwound and not on any previous captures.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Backtrace<const N: usize> {
     /// List of instruction pointer addresses in each frame, from most recent to
     /// oldest.
@@ -200,7 +449,9 @@ pub struct Backtrace<const N: usize> {
     /// they point within the bounds of the caller function. This avoids issues
     /// when a call instruction is the last instruction in a function, which
     /// would otherwise result in a return address pointing at the start of the
-    /// next function.
+    /// next function. The address of a frame that was interrupted by a signal
+    /// or exception is not a return address, so it is recorded unchanged.
+    /// On ARM, the Thumb bit (bit 0) is always cleared.
     pub frames: ArrayVec<usize, N>,
 
     /// Whether any frames have been omitted due to exceeding the capacity of
@@ -212,8 +463,51 @@ impl<const N: usize> Backtrace<N> {
     /// Captures a backtrace from the current call point.
     ///
     /// The first frame of the backtrace is the caller of `Backtrace::capture`.
+    /// This is the same as `Backtrace::capture_skip(0)`.
     #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
     pub fn capture() -> Self {
+        Self::capture_skip(0)
+    }
+
+    /// Captures a backtrace from the current call point into `self`, replacing
+    /// its previous contents.
+    ///
+    /// The first frame of the backtrace is the caller of
+    /// `Backtrace::capture_into`. This is the same as assigning the result of
+    /// [`Backtrace::capture`], but writes the frames directly into existing
+    /// storage, which avoids moving a large backtrace on every sample in a
+    /// tight sampling loop. The unwinder state itself can't be reused between
+    /// captures since it describes the registers at the point of the call.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_into(&mut self) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            self.frames.clear();
+            self.frames_omitted = false;
+            self.fill_from_cursor(&mut Cursor::new(unw_cursor.as_mut_ptr()), true);
+        }
+    }
+
+    /// Captures a backtrace from the current call point, leaving out the
+    /// first `skip` frames.
+    ///
+    /// `skip` counts from the caller of `Backtrace::capture_skip`, so a skip
+    /// of 1 starts the backtrace at the caller's caller. This hides the frames
+    /// of wrappers such as logging helpers or panic hooks. Skipped frames are
+    /// dropped while unwinding and don't count towards the `N` limit.
+    ///
+    /// Note that a wrapper which ends with a call to another function may not
+    /// have a frame of its own if the compiler turned that call into a tail
+    /// call.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_skip(skip: usize) -> Self {
         unsafe {
             let mut unw_context = MaybeUninit::uninit();
             let mut unw_cursor = MaybeUninit::uninit();
@@ -221,72 +515,2191 @@ impl<const N: usize> Backtrace<N> {
             uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
 
             let mut result = Self::default();
-            result.fill_from_cursor(unw_cursor.as_mut_ptr());
+            let mut skip = skip;
+            result.fill_with(&mut Cursor::new(unw_cursor.as_mut_ptr()), true, |_, _| {
+                if skip > 0 {
+                    skip -= 1;
+                    FrameAction::Skip
+                } else {
+                    FrameAction::Record
+                }
+            });
             result
         }
     }
 
-    /// Captures a backtrace from the given register context.
+    /// Captures a backtrace from the current call point, reporting unwinder
+    /// errors.
     ///
-    /// The first frame of the backtrace is the instruction pointer address in
-    /// the register context.
+    /// [`Backtrace::capture`] stops at the first error and returns the frames
+    /// collected up to that point, which makes a failure to unwind look the
+    /// same as a short stack. This instead returns the error reported by
+    /// libunwind if setting up the unwinder, stepping to the next frame or
+    /// reading the instruction pointer of a frame failed.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn try_capture() -> Result<Self, UnwindError> {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            UnwindError::check(uw::unw_getcontext(unw_context.as_mut_ptr()))?;
+            UnwindError::check(uw::unw_init_local(
+                unw_cursor.as_mut_ptr(),
+                unw_context.as_mut_ptr(),
+            ))?;
+
+            let mut result = Self::default();
+            let mut cursor = CheckedCursor::new(Cursor::new(unw_cursor.as_mut_ptr()));
+            result.fill_from_cursor(&mut cursor, true);
+            match cursor.error {
+                Some(err) => Err(err),
+                None => Ok(result),
+            }
+        }
+    }
+
+    /// Captures a backtrace from the current call point with the base address
+    /// set by [`set_global_pic_base`] subtracted from each frame.
     ///
-    /// This function is useful for capturing backtraces from signal handlers
-    /// since the unwinder may not be able to unwind past the signal frame.
+    /// The resulting addresses are relative to the link-time base and can be
+    /// resolved against the binary directly, as described in the
+    /// position-independent code section of the crate documentation.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_global_adjusted() -> Self {
+        let mut result = Self::capture();
+        let base = GLOBAL_PIC_BASE.load(Ordering::Relaxed);
+        for frame in &mut result.frames {
+            *frame = frame.wrapping_sub(base);
+        }
+        result
+    }
+
+    /// Captures a backtrace from the current call point with `base`
+    /// subtracted from each frame.
     ///
-    /// If no unwinding information is found for the instruction pointer address
-    /// in the context then `None` is returned.
-    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
-    pub fn capture_from_context(ctx: &Context) -> Option<Self> {
+    /// This is the adjustment needed for position-independent executables, as
+    /// described in the crate documentation, with `base` typically being the
+    /// value returned by `module_base`. Frames below `base` indicate that
+    /// the wrong base was passed: this triggers a debug assertion, and such
+    /// frames are clamped to 0 in release builds rather than wrapping around.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_relative_to(base: usize) -> Self {
+        let mut result = Self::capture();
+        for frame in &mut result.frames {
+            debug_assert!(
+                *frame >= base,
+                "frame {:#x} is below base {:#x}",
+                frame,
+                base
+            );
+            *frame = frame.saturating_sub(base);
+        }
+        result
+    }
+
+    /// Captures a backtrace from the current call point and tags each frame
+    /// with the module it belongs to.
+    ///
+    /// `resolve` is called with the address of each frame and returns an
+    /// identifier for the loaded object file containing it, or `None` if the
+    /// address is not part of any known module. The crate makes no
+    /// assumptions about how modules are identified; computing the offset of
+    /// a frame within its module is left to the caller.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_with_module<M>(
+        resolve: impl Fn(usize) -> Option<M>,
+    ) -> ArrayVec<(Option<M>, usize), N> {
         unsafe {
             let mut unw_context = MaybeUninit::uninit();
             let mut unw_cursor = MaybeUninit::uninit();
             uw::unw_getcontext(unw_context.as_mut_ptr());
             uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
 
-            // Apply the register state to the cursor.
-            ctx.apply(unw_cursor.as_mut_ptr());
+            let mut result = Self::default();
+            result.fill_from_cursor(&mut Cursor::new(unw_cursor.as_mut_ptr()), true);
+            result
+                .frames
+                .into_iter()
+                .map(|ip| (resolve(ip), ip))
+                .collect()
+        }
+    }
 
-            // Check if we actually have unwind info for the fault address. We
-            // don't generate a backtrace if the fault happened outside our
-            // executable.
-            let mut unw_proc_info = MaybeUninit::uninit();
-            if uw::unw_get_proc_info(unw_cursor.as_mut_ptr(), unw_proc_info.as_mut_ptr())
-                != uw::UNW_ESUCCESS
-            {
-                return None;
-            }
+    /// Captures a backtrace from the current call point along with whether its
+    /// first frame is within `handlers`.
+    ///
+    /// `handlers` is typically the address range of the exception vector table
+    /// and interrupt handlers, as given by linker symbols. The returned flag
+    /// then classifies a crash as having happened in an interrupt rather than
+    /// in mainline code.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_check_handler(handlers: Range<usize>) -> (Self, bool) {
+        let result = Self::capture();
+        let in_handler = result.starts_in(&handlers);
+        (result, in_handler)
+    }
+
+    /// Returns whether the first frame of the backtrace is within `range`.
+    fn starts_in(&self, range: &Range<usize>) -> bool {
+        self.frames.first().is_some_and(|ip| range.contains(ip))
+    }
+
+    /// Captures a backtrace from the current call point, continuing past
+    /// frames that have no unwind information.
+    ///
+    /// Normally unwinding stops at the first frame without unwind information,
+    /// such as hand-written assembly without CFI directives or code from a
+    /// vendor library built without unwind tables, even though the frames
+    /// below it are usually more interesting. In that case, or if the unwinder
+    /// reports an error, this follows the frame pointer of the frame to find
+    /// its caller and continues from there; the frame without unwind
+    /// information itself is not recorded. Frame records are only read if
+    /// they are within `stack`, which should be the bounds of the current
+    /// stack.
+    ///
+    /// This is a heuristic: it assumes that the function without unwind
+    /// information saved a standard frame record, and may skip or misreport a
+    /// frame if it didn't. Bit `i` of the returned mask is set if frame `i` was
+    /// reached this way, for the first 64 frames. Only x86_64, AArch64, ARM
+    /// and RISC-V are supported; on other architectures this behaves like
+    /// [`Backtrace::capture`]. On ARM, the frame pointer is r7 in Thumb code
+    /// and r11 otherwise, in which case both the Clang and the GCC layout of
+    /// the frame record are recognized.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_across_gaps(stack: Range<usize>) -> (Self, u64) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
 
-            // Add the instruction pointer address from the context as the first
-            // frame of the backtrace.
             let mut result = Self::default();
-            result.frames.push(ctx.ip());
-            result.fill_from_cursor(unw_cursor.as_mut_ptr());
-            Some(result)
+            let mut resynced = 0;
+            let mut index = 0;
+            let mut cursor = fp::ResyncCursor::new(unw_cursor.as_mut_ptr(), &stack);
+            result.fill_with(&mut cursor, true, |cursor, _| {
+                if cursor.resynced && index < 64 {
+                    resynced |= 1 << index;
+                }
+                index += 1;
+                FrameAction::Record
+            });
+            (result, resynced)
         }
     }
 
-    unsafe fn fill_from_cursor(&mut self, cursor: *mut uw::unw_cursor_t) {
-        while uw::unw_step(cursor) > 0 {
-            let mut ip = 0;
-            uw::unw_get_reg(cursor, uw::UNW_REG_IP, &mut ip);
+    /// Captures a backtrace from the current call point, unwinding at most
+    /// `max_steps` frames.
+    ///
+    /// Every capture already stops after [`MAX_STEPS`] frames so that a
+    /// corrupted stack which unwinds in a cycle can't hang the caller. This
+    /// allows a lower limit to be used on stacks that are known to be
+    /// unreliable. Frames that are stepped over without being recorded count
+    /// towards `max_steps` but not towards `N`. If unwinding was stopped by
+    /// the limit then `frames_omitted` is set.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_limited(max_steps: usize) -> Self {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut cursor = StepLimit::new(Cursor::new(unw_cursor.as_mut_ptr()), max_steps);
+            result.fill_from_cursor(&mut cursor, true);
+            result.frames_omitted |= cursor.exhausted;
+            result
+        }
+    }
 
-            // Adjust the IP to point within the function symbol. This should
-            // only be done if the frame is not a signal frame.
-            if uw::unw_is_signal_frame(cursor) > 0 {
-                ip -= 1;
-            }
+    /// Captures a backtrace from the current call point, stopping if the stack
+    /// pointer leaves `stack`.
+    ///
+    /// `stack` should be the bounds of the current stack, with `stack.end`
+    /// being the (exclusive) initial stack pointer. After each step, the stack
+    /// pointer of the new frame must be within `stack` and strictly greater
+    /// than that of the previous frame, otherwise unwinding stops and
+    /// `frames_omitted` is set. On a corrupted stack this gives a truncated
+    /// backtrace instead of letting the unwinder read saved registers from
+    /// wild addresses and fault again.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_within(stack: Range<usize>) -> Self {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut cursor = StackBounds::new(Cursor::new(unw_cursor.as_mut_ptr()), &stack);
+            result.fill_from_cursor(&mut cursor, true);
+            result.frames_omitted |= cursor.exceeded;
+            result
+        }
+    }
+
+    /// Captures a backtrace from the current call point along with which of
+    /// its frames are signal frames.
+    ///
+    /// Bit `i` of the returned mask is set if frame `i` is a signal or
+    /// interrupt frame, as reported by the unwind information, for the first
+    /// 64 frames. This marks the boundary between a handler and the code it
+    /// interrupted. These are also the frames whose address was not adjusted
+    /// to point within the call instruction, as described in
+    /// [`Backtrace::frames`].
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_with_signal_frames() -> (Self, u64) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut signal = 0;
+            let mut index = 0;
+            result.fill_with(
+                &mut Cursor::new(unw_cursor.as_mut_ptr()),
+                true,
+                |cursor, _| {
+                    if index < 64 && cursor.is_signal_frame() {
+                        signal |= 1 << index;
+                    }
+                    index += 1;
+                    FrameAction::Record
+                },
+            );
+            (result, signal)
+        }
+    }
+
+    /// Captures a backtrace from the current call point along with the stack
+    /// pointer of the frame at which unwinding stopped.
+    ///
+    /// If the backtrace is full then this is the stack pointer of the first
+    /// frame that was omitted, otherwise it is that of the last frame the
+    /// unwinder could reach. Comparing it against the base of the stack gives
+    /// an idea of how much of the stack was not covered by the backtrace.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_with_stop_sp() -> (Self, usize) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut cursor = Cursor::new(unw_cursor.as_mut_ptr());
+            result.fill_from_cursor(&mut cursor, true);
+            (result, cursor.sp())
+        }
+    }
+
+    /// Captures a backtrace from the current call point along with the stack
+    /// pointer of each frame.
+    ///
+    /// The stack pointers are index-aligned with the frames: `sps[i]` is the
+    /// stack pointer in the frame of `frames[i]`, i.e. the value it had just
+    /// before the call to the next more recent frame. The difference between
+    /// adjacent entries is the amount of stack used by a frame, which helps
+    /// with tracking down stack overflows and corruption.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_with_sp() -> (Self, ArrayVec<usize, N>) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut sps = ArrayVec::new();
+            result.fill_with_sp(&mut Cursor::new(unw_cursor.as_mut_ptr()), true, &mut sps);
+            (result, sps)
+        }
+    }
+
+    /// Captures a backtrace from the current call point along with the amount
+    /// of stack used by each frame.
+    ///
+    /// The sizes are index-aligned with the frames in the same way as for
+    /// [`Backtrace::capture_with_sp`], and are the differences between the
+    /// stack pointers of adjacent frames: `sizes[i]` is the number of bytes
+    /// between the stack pointer of `frames[i]` and that of its caller. The
+    /// caller of the last recorded frame isn't known, so its size is reported
+    /// as 0.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_stack_usage() -> (Self, ArrayVec<usize, N>) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut sps = ArrayVec::<usize, N>::new();
+            result.fill_with_sp(&mut Cursor::new(unw_cursor.as_mut_ptr()), true, &mut sps);
+            let sizes = sps
+                .iter()
+                .enumerate()
+                .map(|(i, &sp)| {
+                    sps.get(i + 1)
+                        .map_or(0, |&caller| caller.saturating_sub(sp))
+                })
+                .collect();
+            (result, sizes)
+        }
+    }
+
+    /// Captures a backtrace from the current call point along with the total
+    /// number of frames on the stack.
+    ///
+    /// Unlike [`Backtrace::capture`], unwinding continues after `frames` is
+    /// full so that the frames which were left out can be counted, which
+    /// helps with choosing `N` or reporting how much of the stack is shown.
+    /// This has the same cost as capturing the whole stack. The count is
+    /// limited to [`MAX_STEPS`]; `frames_omitted` is set if it is larger than
+    /// `N` or if that limit was reached.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_counting() -> (Self, usize) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
 
-            if self.frames.try_push(ip).is_err() {
-                self.frames_omitted = true;
-                break;
+            let mut result = Self::default();
+            let mut total = 0;
+            result.fill_with(&mut Cursor::new(unw_cursor.as_mut_ptr()), true, |_, _| {
+                total += 1;
+                if total <= N {
+                    FrameAction::Record
+                } else {
+                    FrameAction::Skip
+                }
+            });
+            if total > N {
+                result.frames_omitted = true;
             }
+            (result, total)
         }
     }
-}
 
-#[test]
-fn capture() {
-    let bt = Backtrace::<16>::capture();
-    assert!(bt.frames.len() > 1);
+    /// Captures a backtrace from the current call point along with the
+    /// unadjusted instruction pointer of each frame.
+    ///
+    /// The raw addresses are index-aligned with the frames in the same way as
+    /// for [`Backtrace::capture_with_sp`]. They are exactly as reported by
+    /// the unwinder, before the adjustment described in [`Backtrace::frames`]:
+    /// for most frames this is the return address, which can be matched
+    /// against a shadow stack.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_with_raw_ips() -> (Self, ArrayVec<usize, N>) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut raw_ips = ArrayVec::new();
+            result.fill_with(
+                &mut Cursor::new(unw_cursor.as_mut_ptr()),
+                true,
+                |cursor, _| {
+                    // This has the same capacity as `frames`, so the two stay
+                    // aligned.
+                    let _ = raw_ips.try_push(cursor.ip());
+                    FrameAction::Record
+                },
+            );
+            (result, raw_ips)
+        }
+    }
+
+    /// Captures a backtrace from the current call point along with the values
+    /// of some registers in each frame.
+    ///
+    /// `regs` lists libunwind register numbers: the architecture's DWARF
+    /// register numbers, as used in the `UNW_<arch>_*` constants from
+    /// `libunwind.h`, or -1 and -2 for the instruction and stack pointers. The
+    /// values are index-aligned with the frames in the same way as for
+    /// [`Backtrace::capture_with_sp`]: `values[i][j]` is the value of `regs[j]`
+    /// in the frame of `frames[i]`, which is useful for recovering a pointer
+    /// held in a callee-saved register at some point in the call stack. A value
+    /// is `None` if the register isn't valid on this architecture. Note that
+    /// only callee-saved registers are meaningful in frames other than the
+    /// first.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_with_regs<const R: usize>(
+        regs: [i32; R],
+    ) -> (Self, ArrayVec<[Option<usize>; R], N>) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut values = ArrayVec::new();
+            result.fill_with(
+                &mut Cursor::new(unw_cursor.as_mut_ptr()),
+                true,
+                |cursor, _| {
+                    // This has the same capacity as `frames`, so the two stay
+                    // aligned.
+                    let _ = values.try_push(regs.map(|reg| cursor.reg(reg)));
+                    FrameAction::Record
+                },
+            );
+            (result, values)
+        }
+    }
+
+    /// Captures a backtrace from the current call point along with the address
+    /// range of the function containing each frame.
+    ///
+    /// The ranges are index-aligned with the frames in the same way as for
+    /// [`Backtrace::capture_with_sp`], and come from the unwind tables, so
+    /// they can be matched against a symbol map later without running
+    /// `addr2line`. A frame without unwind information gets an empty `0..0`
+    /// range. Looking up the ranges makes this more expensive than
+    /// [`Backtrace::capture`].
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_with_proc_info() -> (Self, ArrayVec<Range<usize>, N>) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut ranges = ArrayVec::new();
+            result.fill_with(
+                &mut Cursor::new(unw_cursor.as_mut_ptr()),
+                true,
+                |cursor, _| {
+                    // This has the same capacity as `frames`, so the two stay
+                    // aligned.
+                    let _ = ranges.try_push(cursor.proc_range().unwrap_or(0..0));
+                    FrameAction::Record
+                },
+            );
+            (result, ranges)
+        }
+    }
+
+    /// Captures a backtrace from the current call point and stamps it with
+    /// `timestamp`.
+    ///
+    /// The crate doesn't read any clock itself: `timestamp` is typically the
+    /// value of a cycle counter or other monotonic counter, which allows
+    /// backtraces from a periodic sampler to be ordered and correlated later.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_stamped(timestamp: u64) -> CapturedAt<N> {
+        CapturedAt {
+            backtrace: Self::capture(),
+            timestamp,
+        }
+    }
+
+    /// Captures a backtrace from the current call point and returns only the
+    /// frames which differ from `prev`.
+    ///
+    /// The returned backtrace holds the innermost frames up to the point
+    /// where the stack matches `prev`, and the returned length is the number
+    /// of outermost frames shared with `prev` that were left out. The full
+    /// backtrace is the returned frames followed by the last
+    /// `shared_suffix_len` frames of `prev`. This compresses successive
+    /// samples where only the top of the stack changes.
+    ///
+    /// If either backtrace has omitted frames, their outermost frames aren't
+    /// the true root of the stack, so no frames are considered shared.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_delta(prev: &Self) -> (Self, usize) {
+        let mut result = Self::capture();
+        let shared = result.shared_suffix_len(prev);
+        result.frames.truncate(result.frames.len() - shared);
+        (result, shared)
+    }
+
+    /// Returns the number of outermost frames which are identical in both
+    /// backtraces.
+    fn shared_suffix_len(&self, other: &Self) -> usize {
+        if self.frames_omitted || other.frames_omitted {
+            return 0;
+        }
+        self.frames
+            .iter()
+            .rev()
+            .zip(other.frames.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count()
+    }
+
+    /// Captures a backtrace from the current call point, keeping only the
+    /// frames whose instruction pointer address falls within one of the
+    /// `allowed` ranges.
+    ///
+    /// Other frames are stepped over without being recorded and don't count
+    /// towards the `N` limit. The returned flag indicates whether any frames
+    /// were left out this way, which is useful when frames from third-party
+    /// code must not appear in crash reports.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_allowlisted(allowed: &[Range<usize>]) -> (Self, bool) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let filtered =
+                result.fill_allowlisted(&mut Cursor::new(unw_cursor.as_mut_ptr()), allowed);
+            (result, filtered)
+        }
+    }
+
+    /// Captures a backtrace from the current call point, starting from the
+    /// first frame whose instruction pointer address is outside of `noisy`.
+    ///
+    /// This is useful for rooting a backtrace at the first "real" caller when
+    /// it is captured from within a logging library or similar code which may
+    /// add any number of wrapper frames. Skipped frames don't count towards
+    /// the `N` limit. Only leading frames are skipped: once a frame outside of
+    /// `noisy` has been found, all following frames are recorded.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_skip_range(noisy: Range<usize>) -> Self {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let mut skipping = true;
+            result.fill_with(&mut Cursor::new(unw_cursor.as_mut_ptr()), true, |_, ip| {
+                skipping &= noisy.contains(&ip);
+                if skipping {
+                    FrameAction::Skip
+                } else {
+                    FrameAction::Record
+                }
+            });
+            result
+        }
+    }
+
+    /// Captures a backtrace from the current call point, ending at the first
+    /// frame for which `stop` returns `true`.
+    ///
+    /// `stop` is called with the address of each frame, adjusted in the same
+    /// way as the recorded frames. The matching frame is recorded as the last
+    /// frame of the backtrace and `frames_omitted` is not set for the frames
+    /// beyond it. This is useful for leaving out the scheduler or boot frames
+    /// below a known thread entry function, which are the same in every
+    /// backtrace.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_until(stop: impl Fn(usize) -> bool) -> Self {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            result.fill_with(&mut Cursor::new(unw_cursor.as_mut_ptr()), true, |_, ip| {
+                if stop(ip) {
+                    FrameAction::RecordLast
+                } else {
+                    FrameAction::Record
+                }
+            });
+            result
+        }
+    }
+
+    /// Captures a backtrace from the current call point with at most one frame
+    /// per function.
+    ///
+    /// Only the most recent frame of each function is recorded, so `N` limits
+    /// the number of distinct functions rather than the number of frames. This
+    /// keeps the report useful for deep recursion involving only a few
+    /// functions. The returned count is the number of frames that were
+    /// collapsed into an earlier frame of the same function before unwinding
+    /// stopped. Frames without unwind information are always recorded.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_distinct_limited() -> (Self, usize) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            let collapsed = result.fill_distinct(&mut Cursor::new(unw_cursor.as_mut_ptr()));
+            (result, collapsed)
+        }
+    }
+
+    /// Captures a backtrace from the given register context.
+    ///
+    /// The first frame of the backtrace is the instruction pointer address in
+    /// the register context.
+    ///
+    /// This function is useful for capturing backtraces from signal handlers
+    /// since the unwinder may not be able to unwind past the signal frame.
+    ///
+    /// If no unwinding information is found for the instruction pointer address
+    /// in the context then `None` is returned.
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "mips",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "riscv64",
+        target_arch = "riscv32"
+    ))]
+    pub fn capture_from_context(ctx: &Context) -> Option<Self> {
+        Self::capture_from_context_inspect(ctx, |_| ()).map(|(bt, ())| bt)
+    }
+
+    /// Captures a backtrace from the given register context along with the
+    /// value of one of its registers.
+    ///
+    /// This is the same as [`Backtrace::capture_from_context`], but also
+    /// returns the value of register `reg` in the context, which is a
+    /// libunwind register number such as `UNW_ARM64_X0 + n` (the DWARF
+    /// register number) or -2 for the stack pointer. This is a convenient way
+    /// to keep a value such as the faulting address with the backtrace. The
+    /// value is `None` if `reg` is not a valid register.
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "mips",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "riscv64",
+        target_arch = "riscv32"
+    ))]
+    pub fn capture_from_context_with_reg(ctx: &Context, reg: i32) -> Option<(Self, Option<usize>)> {
+        Self::capture_from_context_inspect(ctx, |cursor| cursor.reg(reg))
+    }
+
+    /// Implementation of `capture_from_context` which calls `inspect` with the
+    /// cursor for the context, before unwinding it.
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "mips",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "riscv64",
+        target_arch = "riscv32"
+    ))]
+    fn capture_from_context_inspect<R>(
+        ctx: &Context,
+        inspect: impl FnOnce(&mut Cursor) -> R,
+    ) -> Option<(Self, R)> {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            // Apply the register state to the cursor.
+            ctx.apply(unw_cursor.as_mut_ptr());
+            let mut cursor = Cursor::new(unw_cursor.as_mut_ptr());
+
+            // Check if we actually have unwind info for the fault address. We
+            // don't generate a backtrace if the fault happened outside our
+            // executable.
+            cursor.proc_range()?;
+            let inspected = inspect(&mut cursor);
+
+            // Add the instruction pointer address from the context as the first
+            // frame of the backtrace.
+            let mut result = Self::default();
+            result.frames.push(ctx.ip());
+            result.fill_from_cursor(&mut cursor, false);
+            Some((result, inspected))
+        }
+    }
+
+    /// Captures a backtrace from the given register context along with the
+    /// stack pointer of each frame.
+    ///
+    /// This is the same as [`Backtrace::capture_from_context`], with the stack
+    /// pointers returned in the same way as [`Backtrace::capture_with_sp`].
+    /// The stack pointer of the first frame is the one in `ctx`.
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "mips",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "riscv64",
+        target_arch = "riscv32"
+    ))]
+    pub fn capture_from_context_with_sp(ctx: &Context) -> Option<(Self, ArrayVec<usize, N>)> {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            ctx.apply(unw_cursor.as_mut_ptr());
+            let mut cursor = Cursor::new(unw_cursor.as_mut_ptr());
+            cursor.proc_range()?;
+
+            let mut result = Self::default();
+            let mut sps = ArrayVec::new();
+            if result.frames.try_push(ctx.ip()).is_err() {
+                result.frames_omitted = true;
+                return Some((result, sps));
+            }
+            sps.push(ctx.sp());
+            result.fill_with_sp(&mut cursor, false, &mut sps);
+            Some((result, sps))
+        }
+    }
+
+    /// Captures a backtrace from the given register context, choosing where
+    /// the first frame comes from.
+    ///
+    /// With [`InnermostFrame::Pc`] this is the same as
+    /// [`Backtrace::capture_from_context`]. With [`InnermostFrame::Lr`] the
+    /// context is treated as if it had already returned from the current
+    /// function: the first frame is the link register, adjusted in the same way
+    /// as [`Backtrace::frames`], and unwinding continues from there. This is
+    /// useful when the program counter is in a thunk or veneer that doesn't set
+    /// up a stack frame, in which case the link register points into the
+    /// function that is actually of interest.
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "mips",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "riscv64",
+        target_arch = "riscv32"
+    ))]
+    pub fn capture_from_context_with(ctx: &Context, innermost: InnermostFrame) -> Option<Self> {
+        match innermost {
+            InnermostFrame::Pc => Self::capture_from_context(ctx),
+            InnermostFrame::Lr => {
+                let mut result = Self::capture_from_context(&ctx.with_pc_from_lr())?;
+                // The link register is a return address, so it is adjusted
+                // like the frames that follow it.
+                if let Some(first) = result.frames.first_mut() {
+                    *first = steps::call_site(*first);
+                }
+                Some(result)
+            }
+        }
+    }
+
+    /// Captures a backtrace from the current call point and continues it with
+    /// the frames of the interrupted context `ctx`.
+    ///
+    /// This is intended for interrupt or exception handlers, which libunwind
+    /// usually can't unwind out of. The frames of the handler's own stack are
+    /// recorded first, as with [`Backtrace::capture`]. Once unwinding stops,
+    /// the frames from `ctx` are appended in the same way as
+    /// [`Backtrace::capture_from_context`], producing a single backtrace
+    /// across the interrupt boundary.
+    ///
+    /// The returned index is that of the first frame from `ctx`. It is `None`
+    /// if no frames from `ctx` were added because the backtrace was already
+    /// full or no unwinding information was found for its instruction pointer
+    /// address.
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "mips",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "riscv64",
+        target_arch = "riscv32"
+    ))]
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_then_continue(ctx: &Context) -> (Self, Option<usize>) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut result = Self::default();
+            result.fill_from_cursor(&mut Cursor::new(unw_cursor.as_mut_ptr()), true);
+            if result.frames_omitted {
+                return (result, None);
+            }
+
+            // The cursor can't be restarted once unwinding has stopped, so
+            // start again with a new one.
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+            ctx.apply(unw_cursor.as_mut_ptr());
+            let mut cursor = Cursor::new(unw_cursor.as_mut_ptr());
+            if cursor.proc_range().is_none() {
+                return (result, None);
+            }
+
+            let boundary = result.frames.len();
+            if result.frames.try_push(ctx.ip()).is_err() {
+                result.frames_omitted = true;
+                return (result, None);
+            }
+            result.fill_from_cursor(&mut cursor, false);
+            (result, Some(boundary))
+        }
+    }
+
+    /// Captures a backtrace from a Linux `mcontext_t`.
+    ///
+    /// This is a shorthand for building a [`Context`] from the registers in
+    /// `mcontext` and passing it to [`Backtrace::capture_from_context`]. In an
+    /// `SA_SIGINFO` signal handler, `mcontext` is the address of the
+    /// `uc_mcontext` field of the `ucontext_t` passed to the handler.
+    ///
+    /// # Safety
+    ///
+    /// `mcontext` must point to a valid `libc::mcontext_t` for the current
+    /// target.
+    #[cfg(all(
+        feature = "libc",
+        target_os = "linux",
+        any(
+            target_arch = "aarch64",
+            target_arch = "arm",
+            target_arch = "riscv64",
+            target_arch = "riscv32"
+        )
+    ))]
+    pub unsafe fn capture_from_mcontext(mcontext: *const core::ffi::c_void) -> Option<Self> {
+        let ctx = Context::from_mcontext(&*(mcontext as *const libc::mcontext_t));
+        Self::capture_from_context(&ctx)
+    }
+
+    /// Captures a backtrace from a libunwind context saved by other code.
+    ///
+    /// `unw_context` is the address of a `unw_context_t` that was filled in by
+    /// `unw_getcontext`. The first frame of the backtrace is the function
+    /// which called `unw_getcontext`. This allows code which already uses
+    /// libunwind directly to reuse a context instead of capturing a new one.
+    ///
+    /// # Safety
+    ///
+    /// `unw_context` must point to a context saved by the same libunwind that
+    /// this crate uses, since the layout of `unw_context_t` depends on how
+    /// libunwind was built. The function which called `unw_getcontext` must
+    /// not have returned yet.
+    pub unsafe fn capture_from_unw_context(unw_context: *const core::ffi::c_void) -> Self {
+        let mut unw_cursor = MaybeUninit::uninit();
+        uw::unw_init_local(
+            unw_cursor.as_mut_ptr(),
+            unw_context as *mut uw::unw_context_t,
+        );
+        let mut cursor = Cursor::new(unw_cursor.as_mut_ptr());
+
+        let mut result = Self::default();
+        result.frames.push(cursor.frame_ip());
+        result.fill_from_cursor(&mut cursor, false);
+        result
+    }
+
+    /// Captures a backtrace from the register context of a stack overflow
+    /// fault.
+    ///
+    /// `stack` is the range of valid addresses for the stack that overflowed.
+    /// If the stack pointer in `ctx` is within it then this is the same as
+    /// [`Backtrace::capture_from_context`]. Otherwise the faulting function
+    /// was most likely in its prologue, so unwinding it would read its saved
+    /// registers from the guard page and fault again. Instead, the faulting
+    /// frame is recorded as-is and unwinding restarts from its caller, which
+    /// is recovered from the frame pointer and link register. The frame of the
+    /// caller is adjusted in the same way as [`Backtrace::frames`].
+    ///
+    /// This recovery assumes the fault happened before the frame pointer was
+    /// updated and is only reliable if the code is built with frame pointers
+    /// (`-C force-frame-pointers=yes`). `None` is returned if the recovered
+    /// caller frame is not within `stack` either, or if no unwinding
+    /// information is found for it.
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "mips",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "riscv64",
+        target_arch = "riscv32"
+    ))]
+    pub fn capture_from_overflow_context(ctx: &Context, stack: Range<usize>) -> Option<Self> {
+        if stack.contains(&ctx.sp()) {
+            return Self::capture_from_context(ctx);
+        }
+        let caller = ctx.caller_from_link();
+        if !stack.contains(&caller.sp()) {
+            return None;
+        }
+
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            caller.apply(unw_cursor.as_mut_ptr());
+            let mut cursor = Cursor::new(unw_cursor.as_mut_ptr());
+            cursor.proc_range()?;
+
+            // Record both the faulting instruction and the return address in
+            // the caller before continuing to unwind from the caller.
+            let mut result = Self::default();
+            result.frames.push(ctx.ip());
+            if result
+                .frames
+                .try_push(steps::call_site(caller.ip()))
+                .is_err()
+            {
+                result.frames_omitted = true;
+                return Some(result);
+            }
+            result.fill_from_cursor(&mut cursor, false);
+            Some(result)
+        }
+    }
+
+    /// Calls `f` with each pair of adjacent frames as a `(parent, child)`
+    /// edge, from the outermost frame inwards.
+    ///
+    /// The parent is always the caller, i.e. the older of the two frames.
+    /// This is the primitive needed to merge many backtraces into a weighted
+    /// call tree.
+    pub fn edges(&self, mut f: impl FnMut(usize, usize)) {
+        for pair in self.frames.windows(2).rev() {
+            f(pair[1], pair[0]);
+        }
+    }
+
+    /// Returns the smallest and largest distance between the addresses of
+    /// adjacent frames.
+    ///
+    /// Frames on a healthy stack usually lie within the same binary, so a
+    /// very large delta is a cheap hint that the unwinder walked into garbage
+    /// and the backtrace shouldn't be trusted. Returns `None` if there are
+    /// fewer than two frames.
+    pub fn ip_delta_stats(&self) -> Option<IpDeltaStats> {
+        let mut deltas = self.frames.windows(2).map(|w| w[0].abs_diff(w[1]));
+        let first = deltas.next()?;
+        Some(deltas.fold(
+            IpDeltaStats {
+                min_delta: first,
+                max_delta: first,
+            },
+            |stats, delta| IpDeltaStats {
+                min_delta: stats.min_delta.min(delta),
+                max_delta: stats.max_delta.max(delta),
+            },
+        ))
+    }
+
+    /// Fills the backtrace with the frames that follow the current position of
+    /// `cursor`.
+    ///
+    /// If `skip_own` is set then leading frames belonging to this crate's own
+    /// capture functions are not recorded.
+    fn fill_from_cursor(&mut self, cursor: &mut impl UnwindSteps, skip_own: bool) {
+        self.fill_with(cursor, skip_own, |_, _| FrameAction::Record);
+    }
+
+    /// Like `fill_from_cursor`, but skips frames belonging to a function that
+    /// already has a frame in the backtrace. Returns the number of frames
+    /// skipped.
+    fn fill_distinct(&mut self, cursor: &mut impl UnwindSteps) -> usize {
+        let mut seen = ArrayVec::<usize, N>::new();
+        let mut collapsed = 0;
+        self.fill_with(cursor, true, |cursor, _| match cursor.proc_range() {
+            Some(range) if seen.contains(&range.start) => {
+                collapsed += 1;
+                FrameAction::Skip
+            }
+            Some(range) => {
+                let _ = seen.try_push(range.start);
+                FrameAction::Record
+            }
+            None => FrameAction::Record,
+        });
+        collapsed
+    }
+
+    /// Like `fill_from_cursor`, but only records frames within one of the
+    /// `allowed` ranges. Returns whether any frames were left out.
+    fn fill_allowlisted(
+        &mut self,
+        cursor: &mut impl UnwindSteps,
+        allowed: &[Range<usize>],
+    ) -> bool {
+        let mut filtered = false;
+        self.fill_with(cursor, true, |_, ip| {
+            if allowed.iter().any(|range| range.contains(&ip)) {
+                FrameAction::Record
+            } else {
+                filtered = true;
+                FrameAction::Skip
+            }
+        });
+        filtered
+    }
+
+    /// Like `fill_from_cursor`, but also records the stack pointer of each
+    /// frame in `sps`.
+    fn fill_with_sp(
+        &mut self,
+        cursor: &mut impl UnwindSteps,
+        skip_own: bool,
+        sps: &mut ArrayVec<usize, N>,
+    ) {
+        // `sps` has the same capacity as `frames`, so it fills up at the same
+        // frame and the two stay aligned.
+        self.fill_with(cursor, skip_own, |cursor, _| {
+            let _ = sps.try_push(cursor.sp());
+            FrameAction::Record
+        });
+    }
+
+    /// Like `fill_from_cursor`, but calls `action` with the cursor and
+    /// adjusted IP of each frame to decide what to do with it.
+    fn fill_with<C: UnwindSteps>(
+        &mut self,
+        cursor: &mut C,
+        skip_own: bool,
+        action: impl FnMut(&mut C, usize) -> FrameAction,
+    ) {
+        if !fill_frames(&mut self.frames, cursor, skip_own, action) {
+            self.frames_omitted = true;
+        }
+    }
+}
+
+/// Formats the backtrace with one `0x`-prefixed hexadecimal address per line,
+/// followed by a `... <frames omitted>` line if any frames were omitted.
+///
+/// This is the same output as the loop shown in the crate documentation.
+impl<const N: usize> fmt::Display for Backtrace<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for ip in &self.frames {
+            writeln!(f, "  {:#x}", ip)?;
+        }
+        if self.frames_omitted {
+            writeln!(f, " ... <frames omitted>")?;
+        }
+        Ok(())
+    }
+}
+
+/// Logs the frames as a slice of addresses, leaving the formatting to the
+/// host-side decoder.
+#[cfg(feature = "defmt")]
+impl<const N: usize> defmt::Format for Backtrace<N> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Backtrace {{ frames: {=[?]:#x}, frames_omitted: {=bool} }}",
+            self.frames.as_slice(),
+            self.frames_omitted
+        );
+    }
+}
+
+impl Backtrace<0> {
+    /// Walks the stack from the current call point, calling `f` with the
+    /// instruction pointer address of each frame.
+    ///
+    /// Unwinding stops at the end of the stack or as soon as `f` returns
+    /// `false`, or after [`MAX_STEPS`] frames. Since no frames are stored, this
+    /// can be used to stream frames into a log sink or ring buffer. The
+    /// addresses are adjusted in the same way as [`Backtrace::frames`], and the
+    /// first one is the caller of `Backtrace::trace`.
+    ///
+    /// This doesn't depend on the maximum number of frames, so it is called
+    /// as `Backtrace::trace` without specifying `N`.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn trace(mut f: impl FnMut(usize) -> bool) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            fill_frames(
+                &mut TraceSink(&mut f),
+                &mut Cursor::new(unw_cursor.as_mut_ptr()),
+                true,
+                |_, _| FrameAction::Record,
+            );
+        }
+    }
+
+    /// Returns the number of frames on the stack from the current call point.
+    ///
+    /// The count includes the caller of `Backtrace::depth` but not
+    /// `Backtrace::depth` itself, and is the same as the number of frames
+    /// that [`Backtrace::capture`] would return with an unlimited `N`. No
+    /// frames are stored, so this is much cheaper than capturing a backtrace
+    /// just to take its length. The count is limited to [`MAX_STEPS`].
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn depth() -> usize {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            Self::count_frames(&mut Cursor::new(unw_cursor.as_mut_ptr()))
+        }
+    }
+
+    /// Returns a suggested `N` for capturing a backtrace from the current call
+    /// point.
+    ///
+    /// This counts the frames on the stack in the same way as
+    /// [`Backtrace::depth`] and adds [`CAPACITY_MARGIN`] frames, to leave
+    /// space for the slightly deeper stacks that the same call site sees on a
+    /// different path. It is meant as an aid for choosing `N` during
+    /// bring-up, for example by logging it or comparing it against the `N` of
+    /// a later capture.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn recommended_capacity() -> usize {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            Self::count_frames(&mut Cursor::new(unw_cursor.as_mut_ptr())) + CAPACITY_MARGIN
+        }
+    }
+
+    /// Counts the frames that follow the current position of `cursor`, up to
+    /// [`MAX_STEPS`].
+    fn count_frames(cursor: &mut impl UnwindSteps) -> usize {
+        let _guard = CaptureGuard::enter();
+        let mut cursor = Guarded::new(cursor);
+        let mut depth = 0;
+        while cursor.step() {
+            depth += 1;
+        }
+        depth
+    }
+
+    /// Captures a backtrace from the current call point into `out`.
+    ///
+    /// Returns the number of frames written to the start of `out`, and
+    /// whether frames were omitted because `out` was too small. The first
+    /// frame is the caller of `Backtrace::capture_slice`. Like
+    /// [`Backtrace::trace`], this doesn't depend on `N`, so one buffer can be
+    /// reused by capture sites that need different numbers of frames without
+    /// instantiating a capture function for each size.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture_slice(out: &mut [usize]) -> (usize, bool) {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut sink = SliceSink { out, len: 0 };
+            let complete = fill_frames(
+                &mut sink,
+                &mut Cursor::new(unw_cursor.as_mut_ptr()),
+                false,
+                |_, _| FrameAction::Record,
+            );
+            (sink.len, !complete)
+        }
+    }
+}
+
+/// A backtrace along with the time at which it was captured, as returned by
+/// [`Backtrace::capture_stamped`].
+#[derive(Clone, Debug, Default)]
+pub struct CapturedAt<const N: usize> {
+    /// The captured backtrace.
+    pub backtrace: Backtrace<N>,
+
+    /// Counter value passed to `capture_stamped`.
+    pub timestamp: u64,
+}
+
+/// Register used for the first frame by
+/// [`Backtrace::capture_from_context_with`].
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "mips",
+    target_arch = "powerpc",
+    target_arch = "powerpc64",
+    target_arch = "riscv64",
+    target_arch = "riscv32"
+))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InnermostFrame {
+    /// Use the program counter, which is what
+    /// [`Backtrace::capture_from_context`] does.
+    #[default]
+    Pc,
+    /// Use the link register (x30 on AArch64, lr on ARM and PowerPC, r31 on
+    /// MIPS, ra on RISC-V).
+    Lr,
+}
+
+/// Statistics about the distances between adjacent frames of a backtrace,
+/// as returned by [`Backtrace::ip_delta_stats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpDeltaStats {
+    /// Smallest distance between the addresses of two adjacent frames.
+    pub min_delta: usize,
+    /// Largest distance between the addresses of two adjacent frames.
+    pub max_delta: usize,
+}
+
+/// An error reported by libunwind, as returned by [`Backtrace::try_capture`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnwindError {
+    /// Unspecified error (`UNW_EUNSPEC`).
+    Unspecified,
+    /// Out of memory (`UNW_ENOMEM`).
+    NoMemory,
+    /// Invalid register (`UNW_EBADREG`).
+    BadRegister,
+    /// Attempt to write a read-only register (`UNW_EREADONLYREG`).
+    ReadOnlyRegister,
+    /// Stop unwinding (`UNW_ESTOPUNWIND`).
+    StopUnwinding,
+    /// Invalid instruction pointer (`UNW_EINVALIDIP`).
+    InvalidIp,
+    /// Bad frame (`UNW_EBADFRAME`).
+    BadFrame,
+    /// Unsupported operation or bad value (`UNW_EINVAL`).
+    InvalidValue,
+    /// Unwind info has an unsupported version (`UNW_EBADVERSION`).
+    BadVersion,
+    /// No unwind info found (`UNW_ENOINFO`).
+    NoInfo,
+    /// Any other negative return code.
+    Other(i32),
+}
+
+impl UnwindError {
+    /// Converts a libunwind return code to an error if it is negative.
+    pub(crate) fn check(ret: i32) -> Result<(), Self> {
+        if ret >= 0 {
+            return Ok(());
+        }
+        Err(match ret {
+            uw::UNW_EUNSPEC => Self::Unspecified,
+            uw::UNW_ENOMEM => Self::NoMemory,
+            uw::UNW_EBADREG => Self::BadRegister,
+            uw::UNW_EREADONLYREG => Self::ReadOnlyRegister,
+            uw::UNW_ESTOPUNWIND => Self::StopUnwinding,
+            uw::UNW_EINVALIDIP => Self::InvalidIp,
+            uw::UNW_EBADFRAME => Self::BadFrame,
+            uw::UNW_EINVAL => Self::InvalidValue,
+            uw::UNW_EBADVERSION => Self::BadVersion,
+            uw::UNW_ENOINFO => Self::NoInfo,
+            _ => Self::Other(ret),
+        })
+    }
+}
+
+impl fmt::Display for UnwindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unspecified => f.write_str("unspecified unwinder error"),
+            Self::NoMemory => f.write_str("unwinder out of memory"),
+            Self::BadRegister => f.write_str("invalid register"),
+            Self::ReadOnlyRegister => f.write_str("register is read-only"),
+            Self::StopUnwinding => f.write_str("unwinding stopped"),
+            Self::InvalidIp => f.write_str("invalid instruction pointer"),
+            Self::BadFrame => f.write_str("bad frame"),
+            Self::InvalidValue => f.write_str("unsupported operation or bad value"),
+            Self::BadVersion => f.write_str("unsupported unwind info version"),
+            Self::NoInfo => f.write_str("no unwind info found"),
+            Self::Other(code) => write!(f, "unknown unwinder error {}", code),
+        }
+    }
+}
+
+/// What to do with a frame while filling a backtrace.
+pub(crate) enum FrameAction {
+    /// Add the frame to the backtrace.
+    Record,
+    /// Continue unwinding without recording this frame.
+    Skip,
+    /// Add the frame to the backtrace and stop unwinding.
+    RecordLast,
+}
+
+/// Maximum number of frames unwound by a single capture or walk over the
+/// stack.
+///
+/// This is independent of the capacity of the backtrace and protects against
+/// corrupted stacks on which the unwinder never reaches the end, for example
+/// because a saved return address points back into an earlier frame. Captures
+/// which hit this limit have `frames_omitted` set. Unwinding also stops, with
+/// `frames_omitted` set, as soon as a step leaves both the instruction pointer
+/// and the stack pointer unchanged.
+pub const MAX_STEPS: usize = 1024;
+
+/// Number of frames added to the stack depth by
+/// [`Backtrace::recommended_capacity`].
+pub const CAPACITY_MARGIN: usize = 4;
+
+/// Number of captures currently running `fill_frames`.
+#[cfg(debug_assertions)]
+static ACTIVE_CAPTURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Set when a capture starts while another one is still running.
+#[cfg(debug_assertions)]
+static REENTERED: AtomicBool = AtomicBool::new(false);
+
+/// Panics if a capture was started while another one was still unwinding,
+/// since the last call to this function.
+///
+/// The bundled libunwind is built without any locking, so it must not be
+/// entered again while it is unwinding, for example from a signal handler
+/// that interrupted a capture on the same stack. To check this, every capture
+/// function records whether it started while another one was in progress, so
+/// this should be called from normal code after the handler has run, such as
+/// at the end of a test. [`BacktraceIter`] and `BacktraceCursor` only unwind
+/// while one of their methods is running, and are not tracked. Overlapping
+/// captures on different threads are also detected on a best-effort basis.
+///
+/// The tracking is only done in builds with debug assertions; in release
+/// builds this does nothing. Capturing into a `Backtrace` or a slice never
+/// allocates, but `BacktraceVec` does and must not be used in a handler.
+pub fn assert_no_reentrant_capture() {
+    #[cfg(debug_assertions)]
+    {
+        if REENTERED.load(Ordering::Relaxed) {
+            REENTERED.store(false, Ordering::Relaxed);
+            panic!("a backtrace was captured while another capture was in progress");
+        }
+    }
+}
+
+/// Marks a capture as running for as long as it is alive, in builds with debug
+/// assertions.
+struct CaptureGuard {
+    #[cfg(debug_assertions)]
+    prev: usize,
+}
+
+impl CaptureGuard {
+    fn enter() -> Self {
+        // Handlers run to completion before the code they interrupted resumes,
+        // so plain loads and stores are enough to detect nesting. This avoids
+        // depending on atomic read-modify-write operations, which some targets
+        // lack.
+        #[cfg(debug_assertions)]
+        {
+            let prev = ACTIVE_CAPTURES.load(Ordering::Relaxed);
+            if prev != 0 {
+                REENTERED.store(true, Ordering::Relaxed);
+            }
+            ACTIVE_CAPTURES.store(prev + 1, Ordering::Relaxed);
+            Self { prev }
+        }
+        #[cfg(not(debug_assertions))]
+        Self {}
+    }
+}
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        ACTIVE_CAPTURES.store(self.prev, Ordering::Relaxed);
+    }
+}
+
+/// Destination for the frames recorded by `fill_frames`.
+pub(crate) trait FrameSink {
+    /// Adds a frame to the end of the sink. Returns `false` if there is no
+    /// space left for it.
+    fn push(&mut self, ip: usize) -> bool;
+}
+
+impl<const N: usize> FrameSink for ArrayVec<usize, N> {
+    fn push(&mut self, ip: usize) -> bool {
+        self.try_push(ip).is_ok()
+    }
+}
+
+/// Frame sink which writes to the start of a slice.
+pub(crate) struct SliceSink<'a> {
+    pub(crate) out: &'a mut [usize],
+    pub(crate) len: usize,
+}
+
+impl FrameSink for SliceSink<'_> {
+    fn push(&mut self, ip: usize) -> bool {
+        match self.out.get_mut(self.len) {
+            Some(slot) => {
+                *slot = ip;
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Frame sink which passes each frame to the callback of
+/// [`Backtrace::trace`], stopping when it returns `false`.
+struct TraceSink<F>(F);
+
+impl<F: FnMut(usize) -> bool> FrameSink for TraceSink<F> {
+    fn push(&mut self, ip: usize) -> bool {
+        (self.0)(ip)
+    }
+}
+
+/// Adds the frames that follow the current position of `cursor` to `frames`,
+/// calling `action` with the cursor and adjusted IP of each frame to decide
+/// what to do with it.
+///
+/// If `skip_own` is set then leading frames belonging to this crate's own
+/// capture functions are not recorded. Returns `false` if
+/// unwinding stopped because `frames` was full, after [`MAX_STEPS`] frames or
+/// because a step didn't move to a different frame.
+pub(crate) fn fill_frames<C: UnwindSteps>(
+    frames: &mut impl FrameSink,
+    cursor: &mut C,
+    mut skip_own: bool,
+    mut action: impl FnMut(&mut C, usize) -> FrameAction,
+) -> bool {
+    let _guard = CaptureGuard::enter();
+    let mut cursor = Guarded::new(cursor);
+    while cursor.step() {
+        if skip_own {
+            if in_own_function(&mut cursor) {
+                continue;
+            }
+            skip_own = false;
+        }
+
+        let ip = cursor.frame_ip();
+        let last = match action(cursor.inner(), ip) {
+            FrameAction::Record => false,
+            FrameAction::Skip => continue,
+            FrameAction::RecordLast => true,
+        };
+        if !frames.push(ip) {
+            return false;
+        }
+        if last {
+            return true;
+        }
+    }
+    !cursor.truncated()
+}
+
+/// Returns whether the current frame of `cursor` is in one of this crate's own
+/// capture functions.
+///
+/// A backtrace normally starts at the caller of the capture function, but when
+/// one capture function is called from another function in this crate, the
+/// frame of that wrapper would otherwise show up at the top of the backtrace.
+/// All public capture functions are therefore placed in the
+/// `mini_backtrace_capture` section, whose bounds are defined by the linker.
+/// This function is placed there as well so that the section always exists
+/// when it is looked up.
+#[inline(never)]
+#[link_section = "mini_backtrace_capture"]
+pub(crate) fn in_own_function(cursor: &mut impl UnwindSteps) -> bool {
+    extern "C" {
+        // Symbols defined by the linker
+        static __start_mini_backtrace_capture: [u8; 0];
+        static __stop_mini_backtrace_capture: [u8; 0];
+    }
+    let start_ip = match cursor.proc_range() {
+        Some(range) => range.start,
+        None => return false,
+    };
+    let section = unsafe {
+        __start_mini_backtrace_capture.as_ptr() as usize
+            ..__stop_mini_backtrace_capture.as_ptr() as usize
+    };
+    section.contains(&start_ip)
+}
+
+#[test]
+fn capture() {
+    let bt = Backtrace::<16>::capture();
+    assert!(bt.frames.len() > 1);
+}
+
+#[test]
+#[inline(never)]
+fn own_function() {
+    use steps::{MockFrame, MockSteps};
+    let frame = |start: usize| MockFrame {
+        proc_range: Some(start..start + 1),
+        ..Default::default()
+    };
+    let frames = [
+        frame(Backtrace::<4>::capture_counting as *const () as usize),
+        frame(own_function as *const () as usize),
+    ];
+    let mut cursor = MockSteps::new(&frames);
+    assert!(cursor.step());
+    assert!(in_own_function(&mut cursor));
+    assert!(cursor.step());
+    assert!(!in_own_function(&mut cursor));
+}
+
+#[test]
+#[inline(never)]
+fn capture_skip() {
+    #[inline(never)]
+    fn wrapper() -> Backtrace<16> {
+        // Prevent the call from being turned into a tail call, which would
+        // remove this frame.
+        let bt = Backtrace::capture_skip(1);
+        core::hint::black_box(&bt);
+        bt
+    }
+    let direct = Backtrace::<16>::capture();
+    let skipped = wrapper();
+    // Both start at this function, from different call sites.
+    assert_eq!(skipped.frames.len(), direct.frames.len());
+    assert_eq!(skipped.frames[1..], direct.frames[1..]);
+}
+
+#[test]
+fn try_capture() {
+    let bt = Backtrace::<16>::try_capture().unwrap();
+    assert!(bt.frames.len() > 1);
+    assert_eq!(
+        UnwindError::check(uw::UNW_ENOINFO),
+        Err(UnwindError::NoInfo)
+    );
+}
+
+#[test]
+fn display() {
+    use core::fmt::Write;
+    let mut bt = Backtrace::<2>::default();
+    bt.frames.push(0x1234);
+    bt.frames.push(0x5678);
+    bt.frames_omitted = true;
+    let mut out = arrayvec::ArrayString::<64>::new();
+    write!(out, "{}", bt).unwrap();
+    assert_eq!(out.as_str(), "  0x1234\n  0x5678\n ... <frames omitted>\n");
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serde_round_trip() {
+    let bt = Backtrace::<16>::capture();
+    let mut buf = [0; 256];
+    let bytes = postcard::to_slice(&bt, &mut buf).unwrap();
+    let decoded: Backtrace<16> = postcard::from_bytes(bytes).unwrap();
+    assert_eq!(decoded.frames, bt.frames);
+    assert_eq!(decoded.frames_omitted, bt.frames_omitted);
+}
+
+#[test]
+fn capture_into() {
+    let mut bt = Backtrace::<16>::default();
+    bt.capture_into();
+    let first = bt.clone();
+    bt.capture_into();
+    assert_eq!(bt.frames[1..], first.frames[1..]);
+    assert_eq!(bt.frames_omitted, first.frames_omitted);
+}
+
+#[test]
+fn eq_hash() {
+    use core::hash::{Hash, Hasher};
+    struct Fnv(u64);
+    impl Hasher for Fnv {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x100_0000_01b3);
+            }
+        }
+    }
+    let hash = |bt: &Backtrace<1>| {
+        let mut h = Fnv(0xcbf2_9ce4_8422_2325);
+        bt.hash(&mut h);
+        h.finish()
+    };
+    // Only the first frame is kept, so captures from here are identical.
+    #[inline(never)]
+    fn capture_here() -> Backtrace<1> {
+        let bt = Backtrace::capture();
+        core::hint::black_box(&bt);
+        bt
+    }
+
+    let (a, b) = (capture_here(), capture_here());
+    let other = Backtrace::<1>::capture();
+    assert_eq!(a, b);
+    assert_eq!(hash(&a), hash(&b));
+    assert_ne!(a, other);
+    assert_ne!(hash(&a), hash(&other));
+}
+
+#[test]
+fn prints_backtrace() {
+    let mut out = arrayvec::ArrayString::<1024>::new();
+    print_backtrace_on(&mut out).unwrap();
+    assert!(out.starts_with("  0x"));
+}
+
+#[test]
+fn fill_omits_frames_when_full() {
+    use steps::{MockFrame, MockSteps};
+    let frames = [
+        MockFrame {
+            ip: 0x1000,
+            ..Default::default()
+        },
+        MockFrame {
+            ip: 0x2000,
+            signal: true,
+            ..Default::default()
+        },
+        MockFrame {
+            ip: 0x3000,
+            ..Default::default()
+        },
+    ];
+    let mut bt = Backtrace::<2>::default();
+    bt.fill_from_cursor(&mut MockSteps::new(&frames), false);
+    assert_eq!(bt.frames.as_slice(), [0xfff, 0x2000]);
+    assert!(bt.frames_omitted);
+}
+
+#[test]
+fn fill_allowlisted() {
+    use steps::{MockFrame, MockSteps};
+    let frame = |ip| MockFrame {
+        ip,
+        ..Default::default()
+    };
+    let frames = [frame(0x1000), frame(0x8000), frame(0x2000), frame(0x3000)];
+    let allowed = [0..0x1800, 0x1800..0x4000];
+    let mut bt = Backtrace::<2>::default();
+    assert!(bt.fill_allowlisted(&mut MockSteps::new(&frames), &allowed));
+    assert_eq!(bt.frames.as_slice(), [0xfff, 0x1fff]);
+    assert!(bt.frames_omitted);
+
+    let mut bt = Backtrace::<2>::default();
+    assert!(!bt.fill_allowlisted(&mut MockSteps::new(&frames[2..]), &allowed));
+    assert_eq!(bt.frames.as_slice(), [0x1fff, 0x2fff]);
+    assert!(!bt.frames_omitted);
+}
+
+#[test]
+fn fill_stuck_step() {
+    use steps::{MockFrame, MockSteps};
+    let frame = |ip, sp| MockFrame {
+        ip,
+        sp,
+        ..Default::default()
+    };
+    let frames = [
+        frame(0x1000, 0x100),
+        frame(0x1000, 0x200),
+        frame(0x2000, 0x200),
+        frame(0x2000, 0x200),
+        frame(0x3000, 0x300),
+    ];
+    let mut bt = Backtrace::<8>::default();
+    bt.fill_from_cursor(&mut MockSteps::new(&frames), false);
+    assert_eq!(bt.frames.as_slice(), [0xfff, 0xfff, 0x1fff]);
+    assert!(bt.frames_omitted);
+}
+
+#[test]
+fn fill_step_limit() {
+    use steps::{MockFrame, MockSteps};
+    let frames = [
+        MockFrame {
+            ip: 0x1000,
+            ..Default::default()
+        },
+        MockFrame {
+            ip: 0x2000,
+            ..Default::default()
+        },
+    ];
+    let mut bt = Backtrace::<8>::default();
+    let mut cursor = StepLimit::new(MockSteps::new(&frames), 1);
+    bt.fill_from_cursor(&mut cursor, false);
+    assert_eq!(bt.frames.as_slice(), [0xfff]);
+    assert!(cursor.exhausted);
+
+    let mut cursor = StepLimit::new(MockSteps::new(&frames), 2);
+    bt.fill_from_cursor(&mut cursor, false);
+    assert!(!cursor.exhausted);
+}
+
+#[test]
+fn fill_stack_bounds() {
+    use steps::{MockFrame, MockSteps};
+    let frame = |ip, sp| MockFrame {
+        ip,
+        sp,
+        ..Default::default()
+    };
+    let frames = [
+        frame(0x1000, 0x100),
+        frame(0x2000, 0x200),
+        frame(0x3000, 0x180),
+    ];
+    let mut bt = Backtrace::<8>::default();
+    let mut cursor = StackBounds::new(MockSteps::new(&frames[1..]), &(0x100..0x300));
+    bt.fill_from_cursor(&mut cursor, false);
+    assert_eq!(bt.frames.as_slice(), [0x1fff]);
+    assert!(cursor.exceeded);
+
+    let mut bt = Backtrace::<8>::default();
+    let mut cursor = StackBounds::new(MockSteps::new(&frames[..2]), &(0x100..0x200));
+    bt.fill_from_cursor(&mut cursor, false);
+    assert_eq!(bt.frames.as_slice(), [0xfff]);
+    assert!(cursor.exceeded);
+}
+
+#[test]
+fn capture_global_adjusted() {
+    set_global_pic_base(0x10);
+    let adjusted = Backtrace::<16>::capture_global_adjusted();
+    let plain = Backtrace::<16>::capture();
+    assert_eq!(adjusted.frames.len(), plain.frames.len());
+    for (&adjusted, &plain) in adjusted.frames.iter().zip(&plain.frames).skip(1) {
+        assert_eq!(adjusted, plain - 0x10);
+    }
+}
+
+#[test]
+fn capture_relative_to() {
+    let adjusted = Backtrace::<16>::capture_relative_to(0x10);
+    let plain = Backtrace::<16>::capture();
+    assert_eq!(adjusted.frames.len(), plain.frames.len());
+    for (&adjusted, &plain) in adjusted.frames.iter().zip(&plain.frames).skip(1) {
+        assert_eq!(adjusted, plain - 0x10);
+    }
+}
+
+#[test]
+#[inline(never)]
+fn capture_check_handler() {
+    let start = capture_check_handler as *const () as usize;
+    let (_, in_handler) = Backtrace::<16>::capture_check_handler(0..start);
+    assert!(!in_handler);
+    let (_, in_handler) = Backtrace::<16>::capture_check_handler(start..usize::MAX);
+    assert!(in_handler);
+}
+
+#[test]
+fn starts_in() {
+    use steps::{MockFrame, MockSteps};
+    let frames = [
+        MockFrame {
+            ip: 0x1000,
+            ..Default::default()
+        },
+        MockFrame {
+            ip: 0x2000,
+            ..Default::default()
+        },
+    ];
+    let mut bt = Backtrace::<4>::default();
+    assert!(!bt.starts_in(&(0..usize::MAX)));
+    bt.fill_from_cursor(&mut MockSteps::new(&frames), false);
+    assert!(bt.starts_in(&(0xf00..0x1000)));
+    assert!(!bt.starts_in(&(0x1000..0x3000)));
+}
+
+#[test]
+fn capture_with_module() {
+    let frames = Backtrace::<16>::capture_with_module(|ip| Some(ip & 1));
+    assert!(frames.len() > 1);
+    assert!(frames.iter().all(|&(module, ip)| module == Some(ip & 1)));
+}
+
+#[test]
+fn capture_with_stop_sp() {
+    let (bt, full_sp) = Backtrace::<1>::capture_with_stop_sp();
+    assert!(bt.frames_omitted);
+    let (bt, end_sp) = Backtrace::<64>::capture_with_stop_sp();
+    assert!(!bt.frames_omitted);
+    assert!(end_sp > full_sp);
+}
+
+#[test]
+fn capture_with_sp() {
+    let (bt, sps) = Backtrace::<4>::capture_with_sp();
+    assert_eq!(bt.frames.len(), sps.len());
+    assert!(sps.windows(2).all(|w| w[0] <= w[1]));
+}
+
+#[test]
+fn capture_from_unw_context() {
+    let bt = unsafe {
+        let mut unw_context = MaybeUninit::uninit();
+        uw::unw_getcontext(unw_context.as_mut_ptr());
+        Backtrace::<16>::capture_from_unw_context(unw_context.as_ptr() as *const _)
+    };
+    let plain = Backtrace::<16>::capture();
+    assert_eq!(bt.frames.len(), plain.frames.len());
+    assert_eq!(bt.frames[1..], plain.frames[1..]);
+}
+
+#[test]
+fn capture_stack_usage() {
+    let (bt, sizes) = Backtrace::<4>::capture_stack_usage();
+    assert_eq!(bt.frames.len(), sizes.len());
+    assert!(sizes[0] > 0);
+    assert_eq!(sizes.last(), Some(&0));
+}
+
+#[test]
+fn capture_counting() {
+    let (bt, total) = Backtrace::<1>::capture_counting();
+    assert_eq!(bt.frames.len(), 1);
+    assert!(bt.frames_omitted);
+    assert_eq!(total, Backtrace::<64>::capture().frames.len());
+}
+
+#[test]
+fn capture_with_raw_ips() {
+    let (bt, raw_ips) = Backtrace::<4>::capture_with_raw_ips();
+    assert_eq!(bt.frames.len(), raw_ips.len());
+    assert_eq!(bt.frames[0], raw_ips[0] - 1);
+}
+
+#[test]
+#[inline(never)]
+fn capture_with_proc_info() {
+    let (bt, ranges) = Backtrace::<4>::capture_with_proc_info();
+    assert_eq!(bt.frames.len(), ranges.len());
+    assert_eq!(
+        ranges[0].start,
+        capture_with_proc_info as *const () as usize
+    );
+    assert!(ranges[0].contains(&bt.frames[0]));
+}
+
+#[test]
+fn capture_with_signal_frames() {
+    let (bt, signal) = Backtrace::<16>::capture_with_signal_frames();
+    assert!(bt.frames.len() > 1);
+    assert_eq!(signal, 0);
+}
+
+#[test]
+fn capture_with_regs() {
+    let (bt, values) = Backtrace::<4>::capture_with_regs([uw::UNW_REG_SP, 10_000]);
+    assert_eq!(bt.frames.len(), values.len());
+    assert!(values.iter().all(|v| v[0].is_some() && v[1].is_none()));
+}
+
+#[test]
+fn capture_stamped() {
+    let stamped = Backtrace::<16>::capture_stamped(42);
+    let plain = Backtrace::<16>::capture();
+    assert_eq!(stamped.timestamp, 42);
+    assert_eq!(stamped.backtrace.frames[1..], plain.frames[1..]);
+}
+
+#[test]
+fn shared_suffix_len() {
+    let mut a = Backtrace::<4>::default();
+    a.frames.extend([0x1000, 0x2000, 0x3000]);
+    let mut b = Backtrace::<4>::default();
+    b.frames.extend([0x1100, 0x1800, 0x2000, 0x3000]);
+    assert_eq!(a.shared_suffix_len(&b), 2);
+    b.frames_omitted = true;
+    assert_eq!(a.shared_suffix_len(&b), 0);
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+core::arch::global_asm!(
+    // Calls `rdi` with `rsi` as its argument, without any CFI directives.
+    ".globl mini_backtrace_call_without_cfi",
+    "mini_backtrace_call_without_cfi:",
+    "push rbp",
+    "mov rbp, rsp",
+    "mov rax, rdi",
+    "mov rdi, rsi",
+    "call rax",
+    "pop rbp",
+    "ret",
+    // Calls `rdi` with `rsi` as its argument from a known address, with CFI
+    // directives.
+    ".globl mini_backtrace_call_at_label",
+    "mini_backtrace_call_at_label:",
+    ".cfi_startproc",
+    "push rax",
+    ".cfi_adjust_cfa_offset 8",
+    "mov rax, rdi",
+    "mov rdi, rsi",
+    ".globl mini_backtrace_call_site",
+    "mini_backtrace_call_site:",
+    "call rax",
+    "pop rax",
+    ".cfi_adjust_cfa_offset -8",
+    "ret",
+    ".cfi_endproc",
+);
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn frames_point_within_call() {
+    use core::ffi::c_void;
+    extern "C" {
+        fn mini_backtrace_call_at_label(f: extern "C" fn(*mut c_void), arg: *mut c_void);
+        static mini_backtrace_call_site: [u8; 0];
+    }
+    extern "C" fn callback(out: *mut c_void) {
+        unsafe { *(out as *mut Backtrace<2>) = Backtrace::capture() };
+    }
+
+    let mut bt = Backtrace::<2>::default();
+    unsafe { mini_backtrace_call_at_label(callback, &mut bt as *mut _ as *mut c_void) };
+
+    // `call rax` is 2 bytes long, so the return address is 2 bytes after the
+    // call site.
+    let call_site = unsafe { mini_backtrace_call_site.as_ptr() as usize };
+    assert_eq!(bt.frames[1], call_site + 1);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn capture_across_gaps() {
+    use core::ffi::c_void;
+    extern "C" {
+        fn mini_backtrace_call_without_cfi(f: extern "C" fn(*mut c_void), arg: *mut c_void);
+    }
+    type Out = (Backtrace<16>, Backtrace<16>, u64);
+    extern "C" fn callback(out: *mut c_void) {
+        let here = &out as *const _ as usize;
+        let stack = here - 0x10000..here + 0x1000;
+        let (across, resynced) = Backtrace::capture_across_gaps(stack);
+        unsafe { *(out as *mut Out) = (Backtrace::capture(), across, resynced) };
+    }
+
+    let mut out = Out::default();
+    unsafe { mini_backtrace_call_without_cfi(callback, &mut out as *mut Out as *mut c_void) };
+    let (plain, across, resynced) = out;
+
+    // The assembly frame itself is skipped, but its caller is found.
+    assert_eq!(plain.frames.len(), 1);
+    assert!(across.frames.len() > 2);
+    assert_eq!(resynced & 0b11, 0b10);
+}
+
+#[cfg(target_arch = "riscv32")]
+#[test]
+fn capture_from_context_riscv32() {
+    let ctx = unsafe {
+        let mut unw_context = MaybeUninit::uninit();
+        let mut unw_cursor = MaybeUninit::uninit();
+        uw::unw_getcontext(unw_context.as_mut_ptr());
+        uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+        let mut cursor = Cursor::new(unw_cursor.as_mut_ptr());
+        let mut regs = [0; 31];
+        for (i, reg) in regs.iter_mut().enumerate() {
+            *reg = cursor.reg(uw::UNW_RISCV_X1 as i32 + i as i32).unwrap();
+        }
+        Context {
+            pc: cursor.ip(),
+            regs,
+            fregs: [0; 32],
+        }
+    };
+    let bt = Backtrace::<16>::capture_from_context(&ctx).unwrap();
+    assert_eq!(bt.frames[0], ctx.pc);
+    assert!(bt.frames.len() > 1);
+}
+
+#[test]
+fn trace() {
+    let mut frames = 0;
+    Backtrace::trace(|_| {
+        frames += 1;
+        true
+    });
+    assert!(frames > 1);
+    let mut frames = 0;
+    Backtrace::trace(|_| {
+        frames += 1;
+        false
+    });
+    assert_eq!(frames, 1);
+}
+
+#[test]
+fn depth() {
+    let bt = Backtrace::<64>::capture();
+    assert_eq!(Backtrace::depth(), bt.frames.len());
+    assert_eq!(
+        Backtrace::recommended_capacity(),
+        bt.frames.len() + CAPACITY_MARGIN
+    );
+}
+
+#[test]
+fn depth_stuck_step() {
+    use steps::{MockFrame, MockSteps};
+    let frames = [MockFrame::default(), MockFrame::default()];
+    assert_eq!(Backtrace::count_frames(&mut MockSteps::new(&frames)), 1);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "another capture was in progress")]
+fn reentrant_capture() {
+    use steps::{MockFrame, MockSteps};
+    let frames = [MockFrame::default()];
+    let mut bt = Backtrace::<1>::default();
+    bt.fill_with(&mut MockSteps::new(&frames), false, |_, _| {
+        Backtrace::<1>::capture();
+        FrameAction::Record
+    });
+    assert_no_reentrant_capture();
+}
+
+#[test]
+fn capture_slice() {
+    let bt = Backtrace::<64>::capture();
+    let mut buf = [0; 64];
+    let (len, omitted) = Backtrace::capture_slice(&mut buf);
+    assert!(!omitted);
+    assert_eq!(len, bt.frames.len());
+    assert_eq!(buf[1..len], bt.frames[1..]);
+
+    let (len, omitted) = Backtrace::capture_slice(&mut buf[..1]);
+    assert_eq!((len, omitted), (1, true));
+}
+
+#[test]
+fn capture_skip_range() {
+    let bt = Backtrace::<16>::capture_skip_range(0..usize::MAX);
+    assert!(bt.frames.is_empty());
+    let bt = Backtrace::<16>::capture_skip_range(0..0);
+    assert!(bt.frames.len() > 1);
+}
+
+#[test]
+fn capture_until() {
+    let plain = Backtrace::<16>::capture();
+    let root = plain.frames[1];
+    let bt = Backtrace::<16>::capture_until(|ip| ip == root);
+    assert_eq!(bt.frames.len(), 2);
+    assert_eq!(bt.frames[1], root);
+    assert!(!bt.frames_omitted);
+}
+
+#[test]
+fn edges() {
+    let mut bt = Backtrace::<3>::default();
+    bt.frames.extend([0x1000, 0x2000, 0x3000]);
+    let mut edges = ArrayVec::<_, 2>::new();
+    bt.edges(|parent, child| edges.push((parent, child)));
+    assert_eq!(edges.as_slice(), [(0x3000, 0x2000), (0x2000, 0x1000)]);
+}
+
+#[test]
+fn ip_delta_stats() {
+    let mut bt = Backtrace::<4>::default();
+    bt.frames.push(0x1000);
+    assert_eq!(bt.ip_delta_stats(), None);
+    bt.frames.extend([0x1400, 0x1300, 0x9000]);
+    assert_eq!(
+        bt.ip_delta_stats(),
+        Some(IpDeltaStats {
+            min_delta: 0x100,
+            max_delta: 0x7d00,
+        })
+    );
+}
+
+#[test]
+fn fill_distinct() {
+    use steps::{MockFrame, MockSteps};
+    let frame = |ip, start| MockFrame {
+        ip,
+        proc_range: Some(start..start + 0x100),
+        ..Default::default()
+    };
+    let frames = [
+        frame(0x1010, 0x1000),
+        frame(0x2010, 0x2000),
+        frame(0x1020, 0x1000),
+        frame(0x2020, 0x2000),
+        frame(0x3010, 0x3000),
+        frame(0x1030, 0x1000),
+    ];
+    let mut bt = Backtrace::<2>::default();
+    let collapsed = bt.fill_distinct(&mut MockSteps::new(&frames));
+    assert_eq!(bt.frames.as_slice(), [0x100f, 0x200f]);
+    assert_eq!(collapsed, 2);
+    assert!(bt.frames_omitted);
+}
+
+#[test]
+fn fill_skips_own_frames() {
+    use steps::{MockFrame, MockSteps};
+    let capture = Backtrace::<4>::capture as *const () as usize;
+    let frames = [
+        MockFrame {
+            ip: capture + 0x10,
+            proc_range: Some(capture..capture + 0x20),
+            ..Default::default()
+        },
+        MockFrame {
+            ip: 0x1000,
+            ..Default::default()
+        },
+    ];
+    let mut bt = Backtrace::<4>::default();
+    bt.fill_from_cursor(&mut MockSteps::new(&frames), true);
+    assert_eq!(bt.frames.as_slice(), [0xfff]);
+    assert!(!bt.frames_omitted);
 }
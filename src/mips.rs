@@ -0,0 +1,90 @@
+use crate::uw;
+
+/// Register context from which to capture a backtrace.
+///
+/// To start unwinding, `pc` and the stack pointer (r29) must be valid. The
+/// frame pointer (r30) is needed if the function at `pc` uses it to locate
+/// its stack frame, and the return address (r31) is needed if `pc` is in a
+/// leaf function or in a function prologue, before the return address has
+/// been saved on the stack. The other callee-saved registers s0-s7 are only
+/// used for their own values in older frames; if they are unknown, unwinding
+/// still works as long as no older function uses one of them to locate its
+/// stack frame. `hi` and `lo` are never needed for unwinding.
+///
+/// In an interrupt handler, the interrupted `pc` is in the `EPC` coprocessor
+/// register rather than in r31, which holds the return address of the
+/// interrupted function.
+#[derive(Copy, Clone, Debug)]
+pub struct Context {
+    /// Program counter
+    pub pc: u32,
+
+    /// General-purpose registers r0-r31
+    pub regs: [u32; 32],
+
+    /// High word of the multiply/divide result
+    pub hi: u32,
+
+    /// Low word of the multiply/divide result
+    pub lo: u32,
+}
+
+impl Context {
+    /// Creates a context with the given program counter and stack pointer,
+    /// and all other registers set to zero.
+    ///
+    /// The frame pointer and return address should then be set through `regs`
+    /// if they are known.
+    pub fn new(pc: usize, sp: usize) -> Self {
+        let mut regs = [0; 32];
+        regs[29] = sp as u32;
+        Self {
+            pc: pc as u32,
+            regs,
+            hi: 0,
+            lo: 0,
+        }
+    }
+
+    pub(crate) unsafe fn apply(&self, cursor: *mut uw::unw_cursor_t) {
+        uw::unw_set_reg(cursor, uw::UNW_REG_IP, self.pc as usize);
+        for i in 1..32 {
+            uw::unw_set_reg(
+                cursor,
+                (uw::UNW_MIPS_R0 + i) as i32,
+                self.regs[i as usize] as usize,
+            );
+        }
+        uw::unw_set_reg(cursor, uw::UNW_MIPS_HI as i32, self.hi as usize);
+        uw::unw_set_reg(cursor, uw::UNW_MIPS_LO as i32, self.lo as usize);
+    }
+
+    pub(crate) fn ip(&self) -> usize {
+        self.pc as usize
+    }
+
+    pub(crate) fn sp(&self) -> usize {
+        self.regs[29] as usize
+    }
+
+    /// Returns a copy of the context with the return address (r31) as the
+    /// program counter, for when the current function has no stack frame.
+    pub(crate) fn with_pc_from_lr(&self) -> Self {
+        Self {
+            pc: self.regs[31],
+            ..*self
+        }
+    }
+
+    /// Returns the context of the caller as described by the frame pointer
+    /// (r30) and return address (r31), without reading from the stack.
+    ///
+    /// The frame pointer holds the stack pointer of the function that set it
+    /// up, after its stack frame was allocated.
+    pub(crate) fn caller_from_link(&self) -> Self {
+        let mut caller = *self;
+        caller.pc = self.regs[31];
+        caller.regs[29] = self.regs[30];
+        caller
+    }
+}
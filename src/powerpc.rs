@@ -0,0 +1,97 @@
+use crate::uw;
+
+#[cfg(target_arch = "powerpc64")]
+use uw::{UNW_PPC64_CTR as UNW_CTR, UNW_PPC64_LR as UNW_LR, UNW_PPC64_R0 as UNW_R0};
+#[cfg(target_arch = "powerpc")]
+use uw::{UNW_PPC_CTR as UNW_CTR, UNW_PPC_LR as UNW_LR, UNW_PPC_R0 as UNW_R0};
+
+/// Register context from which to capture a backtrace.
+///
+/// To start unwinding, `pc` and the stack pointer (r1) must be valid. The
+/// link register is needed if `pc` is in a leaf function or in a function
+/// prologue, before the link register has been saved on the stack, and the
+/// frame pointer (r31) is needed if the function at `pc` uses it to locate
+/// its stack frame. The other callee-saved registers r14-r30 are only used
+/// for their own values in older frames; if they are unknown, unwinding still
+/// works as long as no older function uses one of them to locate its stack
+/// frame. `ctr` is never needed for unwinding.
+///
+/// In an interrupt handler, the interrupted `pc` is in `SRR0` (or `CSRR0`,
+/// `MCSRR0` for critical and machine check interrupts on e500).
+///
+/// Register save areas are big-endian on most PowerPC systems. The fields of
+/// this struct are in native byte order, so saved registers should be read as
+/// whole words rather than assembled from individual bytes. When a 32-bit
+/// register was saved in a 64-bit slot, its value is in the word at the higher
+/// address.
+#[derive(Copy, Clone, Debug)]
+pub struct Context {
+    /// Program counter
+    pub pc: usize,
+
+    /// General-purpose registers r0-r31
+    pub regs: [usize; 32],
+
+    /// Link register
+    pub lr: usize,
+
+    /// Count register
+    pub ctr: usize,
+}
+
+impl Context {
+    /// Creates a context with the given program counter and stack pointer,
+    /// and all other registers set to zero.
+    ///
+    /// The frame pointer and link register should then be set if they are
+    /// known.
+    pub fn new(pc: usize, sp: usize) -> Self {
+        let mut regs = [0; 32];
+        regs[1] = sp;
+        Self {
+            pc,
+            regs,
+            lr: 0,
+            ctr: 0,
+        }
+    }
+
+    pub(crate) unsafe fn apply(&self, cursor: *mut uw::unw_cursor_t) {
+        uw::unw_set_reg(cursor, uw::UNW_REG_IP, self.pc);
+        for i in 0..32 {
+            uw::unw_set_reg(cursor, (UNW_R0 + i) as i32, self.regs[i as usize]);
+        }
+        uw::unw_set_reg(cursor, UNW_LR as i32, self.lr);
+        uw::unw_set_reg(cursor, UNW_CTR as i32, self.ctr);
+    }
+
+    pub(crate) fn ip(&self) -> usize {
+        self.pc
+    }
+
+    pub(crate) fn sp(&self) -> usize {
+        self.regs[1]
+    }
+
+    /// Returns a copy of the context with the link register as the program
+    /// counter, for when the current function has no stack frame.
+    pub(crate) fn with_pc_from_lr(&self) -> Self {
+        Self {
+            pc: self.lr,
+            ..*self
+        }
+    }
+
+    /// Returns the context of the caller as described by the link register,
+    /// without reading from the stack.
+    ///
+    /// The stack pointer is kept as-is: a function allocates its stack frame
+    /// with a single `stwu`/`stdu` instruction, which leaves r1 unchanged if
+    /// the store faults.
+    pub(crate) fn caller_from_link(&self) -> Self {
+        Self {
+            pc: self.lr,
+            ..*self
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Accumulation of sampled backtraces into a [pprof] profile.
+//!
+//! [pprof]: https://github.com/google/pprof/blob/main/proto/profile.proto
+
+use crate::Backtrace;
+use arrayvec::ArrayVec;
+
+// Indices into the string table of the encoded profile.
+const STR_SAMPLES: u64 = 1;
+const STR_COUNT: u64 = 2;
+const STRING_TABLE: [&str; 3] = ["", "samples", "count"];
+
+// Protobuf wire types.
+const WIRE_VARINT: u8 = 0;
+const WIRE_LEN: u8 = 2;
+
+/// A bounded-memory sampling profile which can be encoded in the pprof
+/// protobuf format.
+///
+/// Each distinct stack added with [`Profile::add`] is stored once along with
+/// the number of times it was seen. Up to `S` distinct stacks of up to `D`
+/// frames each are kept; any further new stacks are counted as dropped.
+///
+/// Addresses are stored as given, so they should already be adjusted for the
+/// load address of the binary (see [`Backtrace::capture_global_adjusted`])
+/// for the profile to be symbolized correctly.
+#[derive(Clone, Debug, Default)]
+pub struct Profile<const S: usize, const D: usize> {
+    samples: ArrayVec<(ArrayVec<usize, D>, u64), S>,
+    dropped: u64,
+}
+
+impl<const S: usize, const D: usize> Profile<S, D> {
+    /// Creates an empty profile.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sampled backtrace to the profile.
+    pub fn add(&mut self, bt: &Backtrace<D>) {
+        if let Some(sample) = self
+            .samples
+            .iter_mut()
+            .find(|(stack, _)| *stack == bt.frames)
+        {
+            sample.1 = sample.1.saturating_add(1);
+        } else if self.samples.try_push((bt.frames.clone(), 1)).is_err() {
+            self.dropped = self.dropped.saturating_add(1);
+        }
+    }
+
+    /// Returns the number of samples which were not recorded because the
+    /// profile already held `S` distinct stacks.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Iterates over all recorded addresses, in the order in which they are
+    /// assigned location IDs.
+    fn addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        self.samples
+            .iter()
+            .flat_map(|(stack, _)| stack.iter().copied())
+    }
+
+    /// Returns the location ID for `addr`, which is one more than the index
+    /// of its first occurrence in `addresses`.
+    fn location_id(&self, addr: usize) -> u64 {
+        self.addresses().position(|a| a == addr).unwrap() as u64 + 1
+    }
+
+    /// Encodes the profile as a pprof `Profile` protobuf message into `buf`.
+    ///
+    /// Returns the number of bytes written, or `None` if `buf` is too small.
+    /// Each sample has a single "samples"/"count" value. Locations are keyed
+    /// by address only, leaving symbolization to pprof. Deduplicating the
+    /// locations takes time quadratic in the number of recorded addresses, so
+    /// this is best done off the hot path, or on the host after transferring
+    /// the profile.
+    pub fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut w = Writer { buf, pos: 0 };
+
+        // sample_type
+        w.key(1, WIRE_LEN)?;
+        w.varint(4)?;
+        w.key(1, WIRE_VARINT)?;
+        w.varint(STR_SAMPLES)?;
+        w.key(2, WIRE_VARINT)?;
+        w.varint(STR_COUNT)?;
+
+        // sample
+        for (stack, count) in &self.samples {
+            let ids_len: usize = stack
+                .iter()
+                .map(|&addr| varint_len(self.location_id(addr)))
+                .sum();
+            let values_len = varint_len(*count);
+            let mut len = 1 + varint_len(values_len as u64) + values_len;
+            if ids_len != 0 {
+                len += 1 + varint_len(ids_len as u64) + ids_len;
+            }
+            w.key(2, WIRE_LEN)?;
+            w.varint(len as u64)?;
+            if ids_len != 0 {
+                w.key(1, WIRE_LEN)?;
+                w.varint(ids_len as u64)?;
+                for &addr in stack {
+                    w.varint(self.location_id(addr))?;
+                }
+            }
+            w.key(2, WIRE_LEN)?;
+            w.varint(values_len as u64)?;
+            w.varint(*count)?;
+        }
+
+        // location
+        for (i, addr) in self.addresses().enumerate() {
+            let id = i as u64 + 1;
+            if self.location_id(addr) != id {
+                continue;
+            }
+            let len = 2 + varint_len(id) + varint_len(addr as u64);
+            w.key(4, WIRE_LEN)?;
+            w.varint(len as u64)?;
+            w.key(1, WIRE_VARINT)?;
+            w.varint(id)?;
+            w.key(3, WIRE_VARINT)?;
+            w.varint(addr as u64)?;
+        }
+
+        // string_table
+        for s in STRING_TABLE {
+            w.key(6, WIRE_LEN)?;
+            w.varint(s.len() as u64)?;
+            w.bytes(s.as_bytes())?;
+        }
+
+        Some(w.pos)
+    }
+}
+
+/// Returns the number of bytes needed to encode `val` as a varint.
+fn varint_len(val: u64) -> usize {
+    (64 - (val | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// Protobuf writer into a fixed-size buffer.
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Option<()> {
+        let end = self.pos.checked_add(bytes.len())?;
+        self.buf.get_mut(self.pos..end)?.copy_from_slice(bytes);
+        self.pos = end;
+        Some(())
+    }
+
+    fn varint(&mut self, mut val: u64) -> Option<()> {
+        while val >= 0x80 {
+            self.bytes(&[val as u8 | 0x80])?;
+            val >>= 7;
+        }
+        self.bytes(&[val as u8])
+    }
+
+    fn key(&mut self, field: u8, wire_type: u8) -> Option<()> {
+        self.bytes(&[field << 3 | wire_type])
+    }
+}
+
+#[test]
+fn pprof_encode() {
+    let mut bt = Backtrace::<2>::default();
+    bt.frames.extend([0x1000, 0x2000]);
+    let mut other = Backtrace::<2>::default();
+    other.frames.extend([0x3000, 0x2000]);
+
+    let mut profile = Profile::<2, 2>::new();
+    profile.add(&bt);
+    profile.add(&bt);
+    profile.add(&other);
+    bt.frames[0] = 0x4000;
+    profile.add(&bt);
+    assert_eq!(profile.dropped(), 1);
+    assert_eq!(profile.location_id(0x2000), 2);
+    assert_eq!(profile.location_id(0x3000), 3);
+
+    let mut buf = [0; 128];
+    let len = profile.encode(&mut buf).unwrap();
+    assert_eq!(buf[..6], [0x0a, 4, 0x08, 1, 0x10, 2]);
+    assert_eq!(buf[6..17], [0x12, 7, 0x0a, 2, 1, 2, 0x12, 1, 2, 0x12, 7]);
+    assert!(profile.encode(&mut buf[..len - 1]).is_none());
+}
@@ -0,0 +1,67 @@
+use crate::steps::Cursor;
+use crate::{uw, FrameAction, FrameSink};
+use core::mem::MaybeUninit;
+
+/// A sink for frames produced by [`capture_into_producer`].
+///
+/// This is typically backed by the producer half of a lock-free
+/// single-producer single-consumer ring buffer, which allows a backtrace to be
+/// captured quickly in an interrupt handler and handed off to a lower-priority
+/// task for formatting or transmission.
+pub trait FrameProducer {
+    /// Called with the instruction pointer address of each frame, from most
+    /// recent to oldest.
+    ///
+    /// The addresses are adjusted in the same way as
+    /// [`Backtrace::frames`](crate::Backtrace::frames). Implementations must
+    /// not block; if there is no space left then the frame should be dropped.
+    fn push_frame(&mut self, ip: usize);
+}
+
+/// Captures a backtrace from the current call point and passes each of its
+/// frames to `prod`.
+///
+/// The first frame passed to `prod` is the caller of `capture_into_producer`,
+/// excluding any wrappers from this crate. Unlike
+/// [`Backtrace`](crate::Backtrace), the number of frames isn't limited by a
+/// capacity: unwinding continues until the end of the stack is reached, or
+/// for at most [`MAX_STEPS`](crate::MAX_STEPS) frames.
+#[inline(never)]
+#[link_section = "mini_backtrace_capture"]
+pub fn capture_into_producer(prod: &mut impl FrameProducer) {
+    unsafe {
+        let mut unw_context = MaybeUninit::uninit();
+        let mut unw_cursor = MaybeUninit::uninit();
+        uw::unw_getcontext(unw_context.as_mut_ptr());
+        uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+        crate::fill_frames(
+            &mut Producer(prod),
+            &mut Cursor::new(unw_cursor.as_mut_ptr()),
+            true,
+            |_, _| FrameAction::Record,
+        );
+    }
+}
+
+/// Adapts a `FrameProducer` to the sink used by `fill_frames`.
+struct Producer<'a, P>(&'a mut P);
+
+impl<P: FrameProducer> FrameSink for Producer<'_, P> {
+    fn push(&mut self, ip: usize) -> bool {
+        self.0.push_frame(ip);
+        true
+    }
+}
+
+#[test]
+fn producer() {
+    struct Frames(arrayvec::ArrayVec<usize, 64>);
+    impl FrameProducer for Frames {
+        fn push_frame(&mut self, ip: usize) {
+            let _ = self.0.try_push(ip);
+        }
+    }
+    let mut frames = Frames(arrayvec::ArrayVec::new());
+    capture_into_producer(&mut frames);
+    assert!(frames.0.len() > 1);
+}
@@ -1,6 +1,18 @@
 use crate::uw;
 
 /// Register context from which to capture a backtrace.
+///
+/// To start unwinding, `pc` and the stack pointer (x2) must be valid. The
+/// frame pointer (s0/x8) is needed if the function at `pc` uses it to locate
+/// its stack frame, and the return address (ra/x1) is needed if `pc` is in a
+/// leaf function or in a function prologue, before the return address has
+/// been saved on the stack. The other callee-saved registers s1-s11 are only
+/// used for their own values in older frames; if they are unknown, unwinding
+/// still works as long as no older function uses one of them to locate its
+/// stack frame.
+///
+/// When inspecting a suspended thread, all of these are usually available
+/// from the register save area written by the context switch.
 #[derive(Copy, Clone, Debug)]
 pub struct Context {
     /// Program counter
@@ -14,6 +26,35 @@ pub struct Context {
 }
 
 impl Context {
+    /// Creates a context with the given program counter and stack pointer,
+    /// and all other registers set to zero.
+    ///
+    /// The frame pointer and return address should then be set through `regs`
+    /// if they are known.
+    pub fn new(pc: usize, sp: usize) -> Self {
+        let mut regs = [0; 31];
+        regs[1] = sp;
+        Self {
+            pc,
+            regs,
+            fregs: [0; 32],
+        }
+    }
+
+    /// Builds a context from the Linux `mcontext_t` of a signal frame.
+    ///
+    /// The floating-point registers are read assuming the D extension.
+    #[cfg(all(feature = "libc", target_os = "linux"))]
+    pub(crate) fn from_mcontext(mcontext: &libc::mcontext_t) -> Self {
+        let mut regs = [0; 31];
+        regs.copy_from_slice(&mcontext.__gregs[1..]);
+        Self {
+            pc: mcontext.__gregs[0] as usize,
+            regs: regs.map(|reg| reg as usize),
+            fregs: unsafe { mcontext.__fpregs.__d.__f },
+        }
+    }
+
     pub(crate) unsafe fn apply(&self, cursor: *mut uw::unw_cursor_t) {
         uw::unw_set_reg(cursor, uw::UNW_REG_IP, self.pc);
         for i in 0..31 {
@@ -28,4 +69,26 @@ impl Context {
     pub(crate) fn ip(&self) -> usize {
         self.pc
     }
+
+    pub(crate) fn sp(&self) -> usize {
+        self.regs[1]
+    }
+
+    /// Returns a copy of the context with the return address (ra) as the
+    /// program counter, for when the current function has no stack frame.
+    pub(crate) fn with_pc_from_lr(&self) -> Self {
+        Self {
+            pc: self.regs[0],
+            ..*self
+        }
+    }
+
+    /// Returns the context of the caller as described by the frame pointer
+    /// (s0) and return address (ra), without reading from the stack.
+    pub(crate) fn caller_from_link(&self) -> Self {
+        let mut caller = *self;
+        caller.pc = self.regs[0];
+        caller.regs[1] = self.regs[7];
+        caller
+    }
 }
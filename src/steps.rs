@@ -0,0 +1,537 @@
+use crate::{uw, UnwindError, MAX_STEPS};
+use core::mem::MaybeUninit;
+use core::ops::Range;
+
+/// Cursor operations used to walk the stack.
+///
+/// All of the frame collection logic is written against this trait rather than
+/// calling libunwind directly, which allows it to be tested with a fabricated
+/// sequence of frames.
+pub(crate) trait UnwindSteps {
+    /// Advances to the next older frame. Returns `false` when the end of the
+    /// stack is reached or if the unwinder encountered an error.
+    fn step(&mut self) -> bool;
+
+    /// Returns the instruction pointer of the current frame, as reported by
+    /// the unwinder.
+    fn ip(&mut self) -> usize;
+
+    /// Returns the stack pointer of the current frame, as reported by the
+    /// unwinder.
+    fn sp(&mut self) -> usize;
+
+    /// Returns whether the current frame is a signal frame.
+    fn is_signal_frame(&mut self) -> bool;
+
+    /// Returns the address range of the function containing the current
+    /// frame, if unwind information is available for it.
+    fn proc_range(&mut self) -> Option<Range<usize>>;
+
+    /// Returns the instruction pointer address to record for the current
+    /// frame.
+    fn frame_ip(&mut self) -> usize {
+        let ip = self.ip();
+
+        // Only adjust the IP if the frame is not a signal frame: the IP of a
+        // frame interrupted by a signal is the address of the interrupted
+        // instruction rather than a return address.
+        if self.is_signal_frame() {
+            strip_thumb_bit(ip)
+        } else {
+            call_site(ip)
+        }
+    }
+}
+
+/// Clears bit 0 of a code address on ARM, which is set if the address is in
+/// Thumb code. libunwind doesn't always clear it.
+fn strip_thumb_bit(ip: usize) -> usize {
+    if cfg!(target_arch = "arm") {
+        ip & !1
+    } else {
+        ip
+    }
+}
+
+/// Returns the address to record for a frame whose IP is the return address
+/// `ip`, adjusted to point within the call instruction and therefore within
+/// the calling function.
+pub(crate) fn call_site(ip: usize) -> usize {
+    strip_thumb_bit(ip).saturating_sub(1)
+}
+
+impl<C: UnwindSteps + ?Sized> UnwindSteps for &mut C {
+    fn step(&mut self) -> bool {
+        (**self).step()
+    }
+
+    fn ip(&mut self) -> usize {
+        (**self).ip()
+    }
+
+    fn sp(&mut self) -> usize {
+        (**self).sp()
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        (**self).is_signal_frame()
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        (**self).proc_range()
+    }
+}
+
+/// A libunwind cursor.
+pub(crate) struct Cursor(*mut uw::unw_cursor_t);
+
+impl Cursor {
+    /// Wraps an initialized libunwind cursor.
+    ///
+    /// The cursor must remain valid for as long as the returned `Cursor` is
+    /// used.
+    pub(crate) unsafe fn new(cursor: *mut uw::unw_cursor_t) -> Self {
+        Self(cursor)
+    }
+
+    /// Returns the value of the given libunwind register in the current
+    /// frame, or `None` if it is not available.
+    pub(crate) fn reg(&mut self, reg: uw::unw_regnum_t) -> Option<usize> {
+        self.try_reg(reg).ok()
+    }
+
+    /// Like `reg`, but returns the libunwind error on failure.
+    pub(crate) fn try_reg(&mut self, reg: uw::unw_regnum_t) -> Result<usize, UnwindError> {
+        let mut val = 0;
+        let ret = unsafe { uw::unw_get_reg(self.0, reg, &mut val) };
+        UnwindError::check(ret)?;
+        Ok(val)
+    }
+
+    /// Like `step`, but returns the libunwind error if unwinding failed
+    /// rather than reaching the end of the stack.
+    pub(crate) fn try_step(&mut self) -> Result<bool, UnwindError> {
+        let ret = unsafe { uw::unw_step(self.0) };
+        UnwindError::check(ret)?;
+        Ok(ret > 0)
+    }
+}
+
+impl UnwindSteps for Cursor {
+    fn step(&mut self) -> bool {
+        unsafe { uw::unw_step(self.0) > 0 }
+    }
+
+    fn ip(&mut self) -> usize {
+        let mut ip = 0;
+        unsafe {
+            uw::unw_get_reg(self.0, uw::UNW_REG_IP, &mut ip);
+        }
+        ip
+    }
+
+    fn sp(&mut self) -> usize {
+        let mut sp = 0;
+        unsafe {
+            uw::unw_get_reg(self.0, uw::UNW_REG_SP, &mut sp);
+        }
+        sp
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        unsafe { uw::unw_is_signal_frame(self.0) > 0 }
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        unsafe {
+            let mut unw_proc_info = MaybeUninit::<uw::unw_proc_info_t>::uninit();
+            if uw::unw_get_proc_info(self.0, unw_proc_info.as_mut_ptr()) != uw::UNW_ESUCCESS {
+                return None;
+            }
+            let unw_proc_info = unw_proc_info.assume_init();
+            Some(unw_proc_info.start_ip..unw_proc_info.end_ip)
+        }
+    }
+}
+
+/// A libunwind cursor stored by value, for walks which are resumed across
+/// several calls.
+///
+/// It must be initialized through `as_mut_ptr` before it is stepped.
+pub(crate) struct OwnedCursor(MaybeUninit<uw::unw_cursor_t>);
+
+impl OwnedCursor {
+    pub(crate) fn uninit() -> Self {
+        Self(MaybeUninit::uninit())
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut uw::unw_cursor_t {
+        self.0.as_mut_ptr()
+    }
+
+    fn cursor(&mut self) -> Cursor {
+        unsafe { Cursor::new(self.0.as_mut_ptr()) }
+    }
+}
+
+impl UnwindSteps for OwnedCursor {
+    fn step(&mut self) -> bool {
+        self.cursor().step()
+    }
+
+    fn ip(&mut self) -> usize {
+        self.cursor().ip()
+    }
+
+    fn sp(&mut self) -> usize {
+        self.cursor().sp()
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        self.cursor().is_signal_frame()
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        self.cursor().proc_range()
+    }
+}
+
+/// Cursor adapter which records the first error reported by libunwind.
+///
+/// Errors from stepping stop unwinding, while errors from reading the
+/// instruction pointer are recorded but unwinding continues.
+pub(crate) struct CheckedCursor {
+    cursor: Cursor,
+    pub(crate) error: Option<UnwindError>,
+}
+
+impl CheckedCursor {
+    pub(crate) fn new(cursor: Cursor) -> Self {
+        Self {
+            cursor,
+            error: None,
+        }
+    }
+
+    fn record<T: Default>(&mut self, result: Result<T, UnwindError>) -> T {
+        result.unwrap_or_else(|err| {
+            self.error.get_or_insert(err);
+            T::default()
+        })
+    }
+}
+
+impl UnwindSteps for CheckedCursor {
+    fn step(&mut self) -> bool {
+        let result = self.cursor.try_step();
+        self.record(result)
+    }
+
+    fn ip(&mut self) -> usize {
+        let result = self.cursor.try_reg(uw::UNW_REG_IP);
+        self.record(result)
+    }
+
+    fn sp(&mut self) -> usize {
+        self.cursor.sp()
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        self.cursor.is_signal_frame()
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        self.cursor.proc_range()
+    }
+}
+
+/// Cursor adapter which stops unwinding when the stack pointer leaves a given
+/// range or fails to increase.
+pub(crate) struct StackBounds<'a, C> {
+    cursor: C,
+    stack: &'a Range<usize>,
+    prev_sp: Option<usize>,
+    /// Set if unwinding was stopped by the bounds check.
+    pub(crate) exceeded: bool,
+}
+
+impl<'a, C: UnwindSteps> StackBounds<'a, C> {
+    pub(crate) fn new(cursor: C, stack: &'a Range<usize>) -> Self {
+        Self {
+            cursor,
+            stack,
+            prev_sp: None,
+            exceeded: false,
+        }
+    }
+}
+
+impl<C: UnwindSteps> UnwindSteps for StackBounds<'_, C> {
+    fn step(&mut self) -> bool {
+        if !self.cursor.step() {
+            return false;
+        }
+        let sp = self.cursor.sp();
+        if !self.stack.contains(&sp) || self.prev_sp.is_some_and(|prev| sp <= prev) {
+            self.exceeded = true;
+            return false;
+        }
+        self.prev_sp = Some(sp);
+        true
+    }
+
+    fn ip(&mut self) -> usize {
+        self.cursor.ip()
+    }
+
+    fn sp(&mut self) -> usize {
+        self.cursor.sp()
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        self.cursor.is_signal_frame()
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        self.cursor.proc_range()
+    }
+}
+
+/// Cursor adapter which stops unwinding after a fixed number of steps.
+pub(crate) struct StepLimit<C> {
+    cursor: C,
+    remaining: usize,
+    /// Set if unwinding was stopped by the limit rather than by reaching the
+    /// end of the stack.
+    pub(crate) exhausted: bool,
+}
+
+impl<C: UnwindSteps> StepLimit<C> {
+    pub(crate) fn new(cursor: C, max_steps: usize) -> Self {
+        Self {
+            cursor,
+            remaining: max_steps,
+            exhausted: false,
+        }
+    }
+}
+
+impl<C: UnwindSteps> UnwindSteps for StepLimit<C> {
+    fn step(&mut self) -> bool {
+        if self.remaining == 0 {
+            // Only report the limit if there actually was another frame.
+            self.exhausted |= self.cursor.step();
+            return false;
+        }
+        self.remaining -= 1;
+        self.cursor.step()
+    }
+
+    fn ip(&mut self) -> usize {
+        self.cursor.ip()
+    }
+
+    fn sp(&mut self) -> usize {
+        self.cursor.sp()
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        self.cursor.is_signal_frame()
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        self.cursor.proc_range()
+    }
+}
+
+/// Cursor adapter which stops unwinding when a step leaves both the
+/// instruction pointer and the stack pointer unchanged.
+///
+/// Malformed unwind information can make a step succeed without moving to a
+/// different frame, which would otherwise repeat the same frame until the
+/// step limit is reached.
+pub(crate) struct StuckCheck<C> {
+    cursor: C,
+    prev: Option<(usize, usize)>,
+    /// Set if unwinding was stopped by the check.
+    pub(crate) stuck: bool,
+}
+
+impl<C: UnwindSteps> StuckCheck<C> {
+    pub(crate) fn new(cursor: C) -> Self {
+        Self {
+            cursor,
+            prev: None,
+            stuck: false,
+        }
+    }
+}
+
+impl<C: UnwindSteps> UnwindSteps for StuckCheck<C> {
+    fn step(&mut self) -> bool {
+        if !self.cursor.step() {
+            return false;
+        }
+        let pos = (self.cursor.ip(), self.cursor.sp());
+        if self.prev == Some(pos) {
+            self.stuck = true;
+            return false;
+        }
+        self.prev = Some(pos);
+        true
+    }
+
+    fn ip(&mut self) -> usize {
+        self.cursor.ip()
+    }
+
+    fn sp(&mut self) -> usize {
+        self.cursor.sp()
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        self.cursor.is_signal_frame()
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        self.cursor.proc_range()
+    }
+}
+
+/// Cursor adapter which every walk over the stack goes through, combining
+/// `StuckCheck` with a `StepLimit` of [`MAX_STEPS`] steps.
+pub(crate) struct Guarded<C> {
+    cursor: StepLimit<StuckCheck<C>>,
+}
+
+impl<C: UnwindSteps> Guarded<C> {
+    pub(crate) fn new(cursor: C) -> Self {
+        Self {
+            cursor: StepLimit::new(StuckCheck::new(cursor), MAX_STEPS),
+        }
+    }
+
+    /// Returns the wrapped cursor.
+    pub(crate) fn inner(&mut self) -> &mut C {
+        &mut self.cursor.cursor.cursor
+    }
+
+    /// Returns whether unwinding was stopped by one of the checks rather than
+    /// by reaching the end of the stack.
+    pub(crate) fn truncated(&self) -> bool {
+        self.cursor.exhausted || self.cursor.cursor.stuck
+    }
+}
+
+impl<C: UnwindSteps> UnwindSteps for Guarded<C> {
+    fn step(&mut self) -> bool {
+        self.cursor.step()
+    }
+
+    fn ip(&mut self) -> usize {
+        self.cursor.ip()
+    }
+
+    fn sp(&mut self) -> usize {
+        self.cursor.sp()
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        self.cursor.is_signal_frame()
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        self.cursor.proc_range()
+    }
+}
+
+/// A frame returned by `MockSteps`.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct MockFrame {
+    pub(crate) ip: usize,
+    pub(crate) sp: usize,
+    pub(crate) signal: bool,
+    pub(crate) proc_range: Option<Range<usize>>,
+}
+
+/// Fake cursor which steps through a fixed list of frames.
+///
+/// Like a real cursor, it starts out on the frame of the capture function
+/// itself, so the first call to `step` moves to the first frame in the list.
+#[cfg(test)]
+pub(crate) struct MockSteps<'a> {
+    frames: &'a [MockFrame],
+    pos: Option<usize>,
+}
+
+#[cfg(test)]
+impl<'a> MockSteps<'a> {
+    pub(crate) fn new(frames: &'a [MockFrame]) -> Self {
+        Self { frames, pos: None }
+    }
+
+    fn frame(&self) -> &MockFrame {
+        &self.frames[self.pos.unwrap()]
+    }
+}
+
+#[cfg(test)]
+impl UnwindSteps for MockSteps<'_> {
+    fn step(&mut self) -> bool {
+        let pos = self.pos.map_or(0, |pos| pos + 1);
+        if pos >= self.frames.len() {
+            return false;
+        }
+        self.pos = Some(pos);
+        true
+    }
+
+    fn ip(&mut self) -> usize {
+        self.frame().ip
+    }
+
+    fn sp(&mut self) -> usize {
+        self.frame().sp
+    }
+
+    fn is_signal_frame(&mut self) -> bool {
+        self.frame().signal
+    }
+
+    fn proc_range(&mut self) -> Option<Range<usize>> {
+        self.frame().proc_range.clone()
+    }
+}
+
+#[test]
+fn frame_ip() {
+    let frames = [
+        MockFrame {
+            ip: 0x1000,
+            ..Default::default()
+        },
+        MockFrame {
+            ip: 0x2000,
+            signal: true,
+            ..Default::default()
+        },
+    ];
+    let mut cursor = MockSteps::new(&frames);
+    assert!(cursor.step());
+    assert_eq!(cursor.frame_ip(), 0xfff);
+    assert!(cursor.step());
+    assert_eq!(cursor.frame_ip(), 0x2000);
+}
+
+#[test]
+#[cfg(target_arch = "arm")]
+fn frame_ip_thumb() {
+    let frames = [MockFrame {
+        ip: 0x1001,
+        ..Default::default()
+    }];
+    let mut cursor = MockSteps::new(&frames);
+    assert!(cursor.step());
+    assert_eq!(cursor.frame_ip(), 0xfff);
+}
@@ -0,0 +1,106 @@
+use crate::steps::{Cursor, UnwindSteps};
+use crate::{uw, FrameAction, FrameSink};
+use arrayvec::ArrayVec;
+use core::cell::Cell;
+use core::mem::MaybeUninit;
+
+/// A fixed table of function start addresses, used by [`capture_indexed`] to
+/// represent frames as small indices.
+///
+/// The table is typically generated at build time from the symbol table of
+/// the binary, so that the host can map each index back to a function name
+/// without needing the binary itself.
+#[derive(Clone, Copy, Debug)]
+pub struct SymbolTable<'a> {
+    starts: &'a [usize],
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Index returned for frames whose function is not in the table.
+    pub const UNKNOWN: u16 = u16::MAX;
+
+    /// Creates a table from a list of function start addresses.
+    ///
+    /// `starts` must be sorted in ascending order and have fewer than
+    /// `u16::MAX` entries; additional entries are never matched.
+    pub const fn new(starts: &'a [usize]) -> Self {
+        Self { starts }
+    }
+
+    /// Returns the index of the function starting at `start_ip`, or
+    /// [`SymbolTable::UNKNOWN`].
+    pub fn index_of(&self, start_ip: usize) -> u16 {
+        match self.starts.binary_search(&start_ip) {
+            Ok(index) if index < Self::UNKNOWN as usize => index as u16,
+            _ => Self::UNKNOWN,
+        }
+    }
+
+    fn fill_from_cursor<const N: usize>(
+        &self,
+        cursor: &mut impl UnwindSteps,
+        frames: &mut ArrayVec<u16, N>,
+    ) {
+        // The index is looked up while the cursor is at the frame, and then
+        // stored by the sink in place of the address.
+        let index = Cell::new(Self::UNKNOWN);
+        let mut sink = IndexSink {
+            frames,
+            index: &index,
+        };
+        crate::fill_frames(&mut sink, cursor, true, |cursor, _| {
+            index.set(match cursor.proc_range() {
+                Some(range) => self.index_of(range.start),
+                None => Self::UNKNOWN,
+            });
+            FrameAction::Record
+        });
+    }
+}
+
+/// Frame sink which records the index of each frame's function instead of its
+/// address.
+struct IndexSink<'a, const N: usize> {
+    frames: &'a mut ArrayVec<u16, N>,
+    index: &'a Cell<u16>,
+}
+
+impl<const N: usize> FrameSink for IndexSink<'_, N> {
+    fn push(&mut self, _ip: usize) -> bool {
+        self.frames.try_push(self.index.get()).is_ok()
+    }
+}
+
+/// Captures a backtrace from the current call point with each frame
+/// represented by the index of its function in `table`.
+///
+/// Functions are identified by their start address as reported by the unwind
+/// tables, so this needs no symbol information on the device. Frames in
+/// functions that aren't in the table are recorded as
+/// [`SymbolTable::UNKNOWN`]. The first frame is the caller of
+/// `capture_indexed`, and at most `N` frames are recorded.
+#[inline(never)]
+#[link_section = "mini_backtrace_capture"]
+pub fn capture_indexed<const N: usize>(table: &SymbolTable<'_>) -> ArrayVec<u16, N> {
+    let mut frames = ArrayVec::new();
+    unsafe {
+        let mut unw_context = MaybeUninit::uninit();
+        let mut unw_cursor = MaybeUninit::uninit();
+        uw::unw_getcontext(unw_context.as_mut_ptr());
+        uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+        table.fill_from_cursor(&mut Cursor::new(unw_cursor.as_mut_ptr()), &mut frames);
+    }
+    frames
+}
+
+#[test]
+#[inline(never)]
+fn indexed() {
+    let starts = [0x10, indexed as *const () as usize];
+    let frames = capture_indexed::<4>(&SymbolTable::new(&starts));
+    assert_eq!(frames[0], 1);
+    assert_eq!(
+        SymbolTable::new(&starts).index_of(0x20),
+        SymbolTable::UNKNOWN
+    );
+}
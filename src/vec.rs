@@ -0,0 +1,74 @@
+use crate::steps::Cursor;
+use crate::{uw, FrameAction, FrameSink};
+use alloc::vec::Vec;
+use core::mem::MaybeUninit;
+
+/// Number of frames reserved up front by [`BacktraceVec::capture`].
+const INITIAL_CAPACITY: usize = 32;
+
+/// A backtrace stored in a `Vec`, with no limit on the number of frames.
+///
+/// This is the same as [`Backtrace`](crate::Backtrace) for targets which have
+/// an allocator: since the list of frames grows as needed, frames are only
+/// left out on stacks deeper than [`MAX_STEPS`](crate::MAX_STEPS), in which
+/// case `frames_omitted` is set.
+#[derive(Clone, Debug, Default)]
+pub struct BacktraceVec {
+    /// List of instruction pointer addresses in each frame, from most recent to
+    /// oldest.
+    ///
+    /// The addresses are adjusted in the same way as
+    /// [`Backtrace::frames`](crate::Backtrace::frames).
+    pub frames: Vec<usize>,
+
+    /// Whether any frames have been omitted because unwinding stopped after
+    /// [`MAX_STEPS`](crate::MAX_STEPS) frames.
+    pub frames_omitted: bool,
+}
+
+impl FrameSink for Vec<usize> {
+    fn push(&mut self, ip: usize) -> bool {
+        self.push(ip);
+        true
+    }
+}
+
+impl BacktraceVec {
+    /// Captures a backtrace from the current call point.
+    ///
+    /// The first frame of the backtrace is the caller of
+    /// `BacktraceVec::capture`. Space for a typical backtrace is reserved
+    /// before unwinding starts, so the `Vec` only needs to grow while
+    /// unwinding for deep stacks.
+    #[inline(never)]
+    #[link_section = "mini_backtrace_capture"]
+    pub fn capture() -> Self {
+        unsafe {
+            let mut unw_context = MaybeUninit::uninit();
+            let mut unw_cursor = MaybeUninit::uninit();
+            uw::unw_getcontext(unw_context.as_mut_ptr());
+            uw::unw_init_local(unw_cursor.as_mut_ptr(), unw_context.as_mut_ptr());
+
+            let mut frames = Vec::with_capacity(INITIAL_CAPACITY);
+            let complete = crate::fill_frames(
+                &mut frames,
+                &mut Cursor::new(unw_cursor.as_mut_ptr()),
+                false,
+                |_, _| FrameAction::Record,
+            );
+            Self {
+                frames,
+                frames_omitted: !complete,
+            }
+        }
+    }
+}
+
+#[test]
+fn backtrace_vec() {
+    let bt = BacktraceVec::capture();
+    let plain = crate::Backtrace::<64>::capture();
+    assert_eq!(bt.frames.len(), plain.frames.len());
+    assert_eq!(bt.frames[1..], plain.frames[1..]);
+    assert!(!bt.frames_omitted);
+}